@@ -0,0 +1,38 @@
+/*
+ * 	sets BUILD_REVISION to the git revision of the tree, the
+ * 	handshake hashes it into the build id of the firmware.
+ * 	a tree with changes that are not committed gets the time
+ * 	of the build after the revision, a tree without git only
+ * 	the time. the simulator runs the same script.
+ */
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let revision = git(&["describe", "--always", "--dirty"]);
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_secs()).unwrap_or(0);
+    let text = match revision {
+        Some(revision) if revision.ends_with("-dirty") => format!("{}-{}", revision, seconds),
+        Some(revision) => revision,
+        None => seconds.to_string(),
+    };
+    println!("cargo:rustc-env=BUILD_REVISION={}", text);
+
+    // Without these cargo runs the script again when anything in the package changes,
+    // the simulator has the firmware sources outside of its package
+    if let (Some(top), Some(git_dir)) = (git(&["rev-parse", "--show-toplevel"]), git(&["rev-parse", "--absolute-git-dir"])) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        println!("cargo:rerun-if-changed={}/index", git_dir);
+        println!("cargo:rerun-if-changed={}/src", top);
+    }
+}
+
+// The trimmed output of a git command run in the package, None if it failed
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok().map(|text| text.trim().to_string())
+}
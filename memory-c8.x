@@ -1,7 +1,9 @@
 /* GD32VF103C8 */
 MEMORY
 {
-	FLASH : ORIGIN = 0x08000000, LENGTH = 64k
+	FLASH : ORIGIN = 0x08000000, LENGTH = 63k
+	/* the last page keeps the settings, see src/role.rs */
+	SETTINGS : ORIGIN = 0x0800FC00, LENGTH = 1k
 	RAM : ORIGIN = 0x20000000, LENGTH = 20k
 }
 
@@ -11,3 +13,5 @@ REGION_ALIAS("REGION_DATA", RAM);
 REGION_ALIAS("REGION_BSS", RAM);
 REGION_ALIAS("REGION_HEAP", RAM);
 REGION_ALIAS("REGION_STACK", RAM);
+
+_settings_start = ORIGIN(SETTINGS);
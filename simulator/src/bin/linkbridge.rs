@@ -0,0 +1,243 @@
+/*
+ * 	connects two ends of the uart link on the host and
+ * 	prints the frames going over it, so a board, the
+ * 	simulator or a recording can stand in for the other
+ * 	board and the two wifi adapters.
+ *
+ * 	cargo run --bin linkbridge -- A B [--record FILE] [--quiet]
+ * 	    forwards the bytes between the endpoints both ways
+ * 	cargo run --bin linkbridge -- replay FILE ENDPOINT [--side 0|1]
+ * 	    sends the bytes one side sent in a recording again,
+ * 	    with the same timing
+ * 	cargo run --bin linkbridge -- check FILE
+ * 	    counts the frames of a recording, the output only
+ * 	    changes when the bytes do
+ * 	cargo run --bin linkbridge -- command ENDPOINT LINE..
+ * 	    sends command lines to a wifi adapter wired to a
+ * 	    usb-uart and prints its events, see adapter.rs
+ *
+ * 	the endpoints are pty, listen:PORT, HOST:PORT or the
+ * 	path of a serial device, see endpoint.rs
+ */
+
+use brm_game as game;
+#[path = "../endpoint.rs"] mod endpoint;
+#[path = "../recording.rs"] mod recording;
+// the commands are written by the firmware, the bridge only needs the prefix
+#[allow(dead_code)] #[path = "../../../src/adapter.rs"] mod adapter;
+
+use std::collections::BTreeMap;
+use std::process;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use endpoint::{Endpoint, Port};
+use game::control::Event;
+use game::link::{Decoder, Frame, FrameKind, LinkError};
+use recording::{Chunk, Recorder};
+
+const SIDES: [&str; 2] = ["a>b", "b>a"];
+
+fn usage() -> ! {
+    eprintln!("usage: linkbridge A B [--record FILE] [--quiet]");
+    eprintln!("       linkbridge replay FILE ENDPOINT [--side 0|1]");
+    eprintln!("       linkbridge check FILE");
+    eprintln!("       linkbridge command ENDPOINT LINE..");
+    eprintln!("endpoints: pty, listen:PORT, HOST:PORT or a device path");
+    process::exit(2);
+}
+
+fn endpoint(text: &str) -> Endpoint {
+    Endpoint::parse(text).unwrap_or_else(|| {
+        eprintln!("unknown endpoint '{}'", text);
+        usage()
+    })
+}
+
+fn open(endpoint: &Endpoint) -> Port {
+    endpoint.open().unwrap_or_else(|e| {
+        eprintln!("cannot open {:?}: {}", endpoint, e);
+        process::exit(1)
+    })
+}
+
+fn print_frame(side: usize, result: Result<Frame, LinkError>) {
+    match result {
+        Ok(frame) if frame.kind == FrameKind::Adapter => println!("{} Adapter seq {} {:?}",
+            SIDES[side], frame.seq, Event::decode(frame.payload())),
+        Ok(frame) => println!("{} {:?} from {} seq {} {:02x?}",
+            SIDES[side], frame.kind, frame.source, frame.seq, frame.payload()),
+        Err(error) => println!("{} {:?}", SIDES[side], error),
+    }
+}
+
+fn bridge(a: Endpoint, b: Endpoint, record: Option<String>, quiet: bool) {
+    let mut ports = [open(&a), open(&b)];
+    eprintln!("bridging {} and {}", ports[0].name, ports[1].name);
+    let receivers: Vec<Receiver<Vec<u8>>> = ports.iter_mut().map(|p| p.spawn_reader()).collect();
+    let mut decoders = [Decoder::new(), Decoder::new()];
+    let mut recorder = record.map(|path| Recorder::create(&path).unwrap_or_else(|e| {
+        eprintln!("cannot create {}: {}", path, e);
+        process::exit(1)
+    }));
+
+    loop {
+        let mut idle = true;
+        for side in 0..2 {
+            let bytes = match receivers[side].try_recv() {
+                Ok(bytes) => bytes,
+                Err(TryRecvError::Empty) => continue,
+                Err(TryRecvError::Disconnected) => {
+                    eprintln!("{} closed", ports[side].name);
+                    return;
+                }
+            };
+            idle = false;
+
+            if let Err(e) = ports[1 - side].write(&bytes) {
+                eprintln!("cannot write to {}: {}", ports[1 - side].name, e);
+                return;
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                recorder.record(side as u8, &bytes).expect("cannot write the recording");
+            }
+            if !quiet {
+                decoders[side].feed(&bytes, |result| print_frame(side, result));
+            }
+        }
+        if idle {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn replay(path: &str, to: Endpoint, side: u8) {
+    let chunks = recording::load(path).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", path, e);
+        process::exit(1)
+    });
+    let mut port = open(&to);
+    let answers = port.spawn_reader();
+    let mut decoder = Decoder::new();
+    let start = Instant::now();
+
+    for chunk in chunks.iter().filter(|c| c.side == side) {
+        let at = Duration::from_millis(chunk.millis as u64);
+        // The answers are printed while waiting for the time of the next chunk
+        while start.elapsed() < at {
+            while let Ok(bytes) = answers.try_recv() {
+                decoder.feed(&bytes, |result| print_frame(1 - side as usize, result));
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+        if let Err(e) = port.write(&chunk.bytes) {
+            eprintln!("cannot write to {}: {}", port.name, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn check(path: &str) {
+    let chunks = recording::load(path).unwrap_or_else(|e| {
+        eprintln!("cannot read {}: {}", path, e);
+        process::exit(1)
+    });
+    print!("{}", summary(&chunks));
+}
+
+// the bytes and the frames of each kind sent by both sides
+fn summary(chunks: &[Chunk]) -> String {
+    let mut decoders = [Decoder::new(), Decoder::new()];
+    let mut kinds: [BTreeMap<String, u32>; 2] = [BTreeMap::new(), BTreeMap::new()];
+    let mut bytes = [0usize; 2];
+
+    for chunk in chunks.iter() {
+        let side = chunk.side as usize;
+        let counts = &mut kinds[side];
+        bytes[side] += chunk.bytes.len();
+        decoders[side].feed(&chunk.bytes, |result| {
+            if let Ok(frame) = result {
+                *counts.entry(format!("{:?}", frame.kind)).or_insert(0) += 1;
+            }
+        });
+    }
+
+    let mut out = String::new();
+    for side in 0..2 {
+        out += &format!("{} {} bytes {:?}\n", SIDES[side], bytes[side], decoders[side].stats);
+        for (kind, count) in kinds[side].iter() {
+            out += &format!("{}   {:<18} {}\n", SIDES[side], kind, count);
+        }
+    }
+    out
+}
+
+fn command(to: Endpoint, lines: &[String]) {
+    let mut port = open(&to);
+    let answers = port.spawn_reader();
+    let mut decoder = Decoder::new();
+    for line in lines {
+        let text = format!("{}{}\r\n", adapter::COMMAND_PREFIX, line);
+        if let Err(e) = port.write(text.as_bytes()) {
+            eprintln!("cannot write to {}: {}", port.name, e);
+            process::exit(1);
+        }
+        // The adapter sends its other events too, the answer is among them
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(500) {
+            while let Ok(bytes) = answers.try_recv() {
+                decoder.feed(&bytes, |result| match result {
+                    Ok(frame) if frame.kind == FrameKind::Adapter => println!("> {:?}", Event::decode(frame.payload())),
+                    _ => {}
+                });
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("check") if args.len() == 2 => check(&args[1]),
+        Some("command") if args.len() >= 3 => command(endpoint(&args[1]), &args[2..]),
+        Some("replay") if args.len() >= 3 => {
+            let side = match args[3..].iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+                [] => 0,
+                ["--side", side] => side.parse().ok().filter(|s| *s <= 1).unwrap_or_else(|| usage()),
+                _ => usage(),
+            };
+            replay(&args[1], endpoint(&args[2]), side);
+        }
+        Some(_) if args.len() >= 2 => {
+            let mut record = None;
+            let mut quiet = false;
+            let mut rest = args[2..].iter();
+            while let Some(arg) = rest.next() {
+                match arg.as_str() {
+                    "--record" => record = Some(rest.next().unwrap_or_else(|| usage()).clone()),
+                    "--quiet" => quiet = true,
+                    _ => usage(),
+                }
+            }
+            bridge(endpoint(&args[0]), endpoint(&args[1]), record, quiet);
+        }
+        _ => usage(),
+    }
+}
+
+// A saved recording of a short match with split reads and damaged frames, check
+// has to count it the same way as when the summary was saved
+#[cfg(test)]
+mod tests {
+    use super::{recording, summary};
+
+    #[test]
+    fn check_matches_the_saved_summary() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+        let chunks = recording::load(&format!("{}/match.bin", dir)).unwrap();
+        let saved = std::fs::read_to_string(format!("{}/match.check", dir)).unwrap();
+        assert_eq!(summary(&chunks), saved);
+    }
+}
@@ -0,0 +1,160 @@
+/*
+ * 	the host end of the uart link between a board and its
+ * 	wifi adapter, without extra dependencies
+ *
+ * 	pty            a new pseudo terminal, its path is printed
+ * 	listen:PORT    waits for one tcp connection on the port
+ * 	HOST:PORT      connects to a tcp socket
+ * 	PATH           an existing serial device or pty, e.g.
+ * 	               /dev/ttyUSB0 of a usb-uart on a board
+ *
+ * 	the bytes read are handed over on a channel by a thread
+ */
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::os::unix::io::FromRawFd;
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+// the baud rate of the uart between the boards and the adapters
+pub const BAUD_RATE: u32 = 74880;
+
+const O_RDWR: i32 = 0o2;
+const O_NOCTTY: i32 = 0o400;
+
+extern "C" {
+    fn posix_openpt(flags: i32) -> i32;
+    fn grantpt(fd: i32) -> i32;
+    fn unlockpt(fd: i32) -> i32;
+    fn ptsname(fd: i32) -> *const std::os::raw::c_char;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Pty,
+    Listen(u16),
+    Connect(String),
+    Device(String),
+}
+
+impl Endpoint {
+    pub fn parse(text: &str) -> Option<Self> {
+        if text == "pty" {
+            Some(Endpoint::Pty)
+        } else if let Some(port) = text.strip_prefix("listen:") {
+            port.parse().ok().map(Endpoint::Listen)
+        } else if text.starts_with('/') {
+            Some(Endpoint::Device(text.to_string()))
+        } else if text.contains(':') {
+            Some(Endpoint::Connect(text.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// opens the endpoint, a listening socket waits for the connection
+    pub fn open(&self) -> io::Result<Port> {
+        match self {
+            Endpoint::Pty => open_pty(),
+            Endpoint::Listen(port) => {
+                let listener = TcpListener::bind(("127.0.0.1", *port))?;
+                eprintln!("waiting for a connection on 127.0.0.1:{}", port);
+                let (stream, peer) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Port::new(format!("tcp {}", peer), Box::new(stream.try_clone()?), Box::new(stream), None)
+            }
+            Endpoint::Connect(address) => {
+                let stream = TcpStream::connect(address.as_str())?;
+                stream.set_nodelay(true)?;
+                Port::new(format!("tcp {}", address), Box::new(stream.try_clone()?), Box::new(stream), None)
+            }
+            Endpoint::Device(path) => {
+                let file = OpenOptions::new().read(true).write(true).open(path)?;
+                // A pty of another tool or a usb-uart, raw bytes at the speed of the boards
+                let _ = raw_mode(path);
+                Port::new(path.clone(), Box::new(file.try_clone()?), Box::new(file), None)
+            }
+        }
+    }
+}
+
+/// an open endpoint
+pub struct Port {
+    pub name: String,
+    reader: Option<Box<dyn Read + Send>>,
+    writer: Box<dyn Write + Send>,
+    // the pty side kept open, reading the master fails while nobody has it open
+    _slave: Option<File>,
+}
+
+impl Port {
+    fn new(name: String, reader: Box<dyn Read + Send>, writer: Box<dyn Write + Send>, slave: Option<File>) -> io::Result<Self> {
+        Ok(Port { name, reader: Some(reader), writer, _slave: slave })
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.writer.flush()
+    }
+
+    /// spawns a thread sending the chunks read to the channel, it is closed when the endpoint is
+    pub fn spawn_reader(&mut self) -> Receiver<Vec<u8>> {
+        let (sender, receiver) = mpsc::channel();
+        let mut reader = self.reader.take().expect("the reader was already spawned");
+        thread::spawn(move || {
+            let mut buf = [0u8; 256];
+            loop {
+                match reader.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(count) => {
+                        if sender.send(buf[..count].to_vec()).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(_) => break,
+                }
+            }
+        });
+        receiver
+    }
+}
+
+fn open_pty() -> io::Result<Port> {
+    let (master, path) = unsafe {
+        let fd = posix_openpt(O_RDWR | O_NOCTTY);
+        if fd < 0 || grantpt(fd) != 0 || unlockpt(fd) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let name = ptsname(fd);
+        if name.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let path = std::ffi::CStr::from_ptr(name).to_string_lossy().into_owned();
+        (File::from_raw_fd(fd), path)
+    };
+
+    let slave = OpenOptions::new().read(true).write(true).open(&path)?;
+    raw_mode(&path)?;
+    eprintln!("pty at {}", path);
+    Port::new(path, Box::new(master.try_clone()?), Box::new(master), Some(slave))
+}
+
+// no echo and no line editing, the frames are binary
+fn raw_mode(path: &str) -> io::Result<()> {
+    let status = Command::new("stty")
+        .args(["-F", path, "raw", "-echo", &BAUD_RATE.to_string()])
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        // Not every device takes the odd baud rate, raw mode is enough for a pty
+        let status = Command::new("stty").args(["-F", path, "raw", "-echo"]).status()?;
+        if !status.success() {
+            return Err(io::Error::other("stty failed"));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+/*
+ * 	maps keyboard presses to nunchuk controller inputs
+ *
+ * 	a terminal only reports key presses, not releases, so
+ * 	the fire key toggles the z-button: first press starts
+ * 	aiming, the second one releases the trigger and shoots.
+ * 	while aiming the direction keys turn the aim instead of
+ * 	moving the player.
+ */
+
+use crate::input::ControllerInput;
+use crate::terminal::Key;
+
+// joystick deflection of a pressed direction key, over the moving threshold
+const JOY_FULL: i8 = 120;
+
+pub struct Bindings {
+    pub up: Key,
+    pub down: Key,
+    pub left: Key,
+    pub right: Key,
+    pub fire: Key,
+    // the nunchuk C button, used for getting ready in the lobby
+    pub ready: Key,
+}
+
+pub const PLAYER_1_KEYS: Bindings = Bindings {
+    up: Key::Char('w'),
+    down: Key::Char('s'),
+    left: Key::Char('a'),
+    right: Key::Char('d'),
+    fire: Key::Char(' '),
+    ready: Key::Char('c'),
+};
+
+pub const PLAYER_2_KEYS: Bindings = Bindings {
+    up: Key::Up,
+    down: Key::Down,
+    left: Key::Left,
+    right: Key::Right,
+    fire: Key::Enter,
+    ready: Key::Char('m'),
+};
+
+pub struct KeyboardController {
+    bindings: Bindings,
+    // direction pressed during the current tick
+    pending: (i8, i8),
+    // direction kept while the trigger is held
+    aim: (i8, i8),
+    btn_z: bool,
+    // the trigger was released since the last tick
+    released: bool,
+    // C button pressed during the current tick
+    btn_c: bool,
+}
+
+impl KeyboardController {
+    /// aim_y is the initial aiming direction, towards the opponent
+    pub fn new(bindings: Bindings, aim_y: i8) -> Self {
+        KeyboardController {
+            bindings,
+            pending: (0, 0),
+            aim: (0, aim_y.signum() * JOY_FULL),
+            btn_z: false,
+            released: false,
+            btn_c: false,
+        }
+    }
+
+    // returns true if the key belongs to this controller
+    pub fn handle(&mut self, key: Key) -> bool {
+        let b = &self.bindings;
+        if key == b.up {
+            self.pending.1 = -JOY_FULL;
+        } else if key == b.down {
+            self.pending.1 = JOY_FULL;
+        } else if key == b.left {
+            self.pending.0 = -JOY_FULL;
+        } else if key == b.right {
+            self.pending.0 = JOY_FULL;
+        } else if key == b.fire {
+            self.released = self.btn_z;
+            self.btn_z = !self.btn_z;
+        } else if key == b.ready {
+            self.btn_c = true;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// the controller state for this tick, call once per frame
+    pub fn input(&mut self) -> ControllerInput {
+        if self.btn_z && self.pending != (0, 0) {
+            self.aim = self.pending;
+        }
+
+        // the aim is kept also on the tick the trigger is released,
+        // the projectile is shot in the joystick direction of that tick
+        let (joy_x, joy_y) = if self.btn_z || self.released {
+            self.aim
+        } else {
+            self.pending
+        };
+        self.pending = (0, 0);
+        self.released = false;
+        let btn_c = self.btn_c;
+        self.btn_c = false;
+
+        ControllerInput {
+            joy_x,
+            joy_y,
+            btn_z: self.btn_z as u8,
+            btn_c: btn_c as u8,
+            ..ControllerInput::empty()
+        }
+    }
+}
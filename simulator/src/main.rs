@@ -0,0 +1,445 @@
+/*
+ * 	host-side simulator for the wireless game
+ *
+ * 	runs the same game code as the firmware on the
+ * 	master device, renders the 16x16 led matrix as colored
+ * 	blocks in the terminal and reads both players from
+ * 	the keyboard.
+ *
+ * 	player 1: w a s d, space to start aiming / shoot, c ready
+ * 	player 2: arrow keys, enter to start aiming / shoot, m ready
+ * 	r goes back to the lobby, q quits
+ *
+ * 	cargo run -- [classic|fast|chaotic] [easy|normal|hard] [2|3|4]
+ * 	the difficulty replaces player 2 with the computer, the
+ * 	players after the keyboard ones are always computers
+ *
+ * 	the matrix is drawn from the screen frames the master
+ * 	streams to the clients, so it shows what a client sees
+ *
+ * 	cargo run -- --master ENDPOINT
+ * 	    plays the master of a real link, the other players
+ * 	    are the clients connected to the endpoint
+ * 	cargo run -- --client ENDPOINT
+ * 	    plays player 1 as a client of the master at the
+ * 	    endpoint, with the keys of player 1
+ *
+ * 	the endpoints are the same as for the link bridge
+ */
+
+// the game logic is shared with the firmware, the firmware also uses the parts of
+// its modules the simulator leaves out
+use brm_game as game;
+#[allow(dead_code)] #[path = "../../src/input.rs"] mod input;
+#[allow(dead_code)] #[path = "../../src/engine.rs"] mod engine;
+#[allow(dead_code)] #[path = "../../src/rules.rs"] mod rules;
+#[allow(dead_code)] #[path = "../../src/matchstate.rs"] mod matchstate;
+#[path = "../../src/ai.rs"] mod ai;
+#[allow(dead_code)] #[path = "../../src/screen.rs"] mod screen;
+#[allow(dead_code)] #[path = "../../src/stream.rs"] mod stream;
+#[allow(dead_code)] #[path = "../../src/handshake.rs"] mod handshake;
+// the link quality and lockstep of the firmware, for their unit tests
+#[cfg(test)]
+#[allow(dead_code)] #[path = "../../src/latency.rs"] mod latency;
+// the firmware toolchain predates is_multiple_of
+#[cfg(test)]
+#[allow(clippy::manual_is_multiple_of)]
+#[allow(dead_code)] #[path = "../../src/lockstep.rs"] mod lockstep;
+// the forwarding of the wifi adapter, for its unit tests
+#[cfg(test)]
+#[path = "../../wifi-adapter/src/net/forward.rs"] mod forward;
+// the command lines of the wifi adapter, for their unit tests
+#[cfg(test)]
+#[path = "../../wifi-adapter/src/command/reader.rs"] mod reader;
+
+mod endpoint;
+mod keyboard;
+mod terminal;
+
+use std::fmt::Write as FmtWrite;
+use std::io::{self, Write};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use endpoint::{Endpoint, Port};
+use engine::{Outcome, MAX_PLAYERS};
+use handshake::{Ack, Controller, Hello, Peers};
+// the adapter events of forward.rs
+#[cfg(test)]
+use game::control;
+use game::link;
+use rules::GameRules;
+use matchstate::{Match, Phase};
+use ai::{AiController, Difficulty};
+use input::ControllerInput;
+use screen::{Screen, Shade};
+use stream::{ScreenReceiver, ScreenSender};
+use keyboard::{KeyboardController, PLAYER_1_KEYS, PLAYER_2_KEYS};
+use terminal::{Key, RawTerminal};
+
+// the firmware sleeps this long at the end of every frame
+const TICK: Duration = Duration::from_millis(100);
+// rounds needed to win the match, same as the firmware
+const MAX_SCORE: u8 = 3;
+// player id of a client, the firmware saves it with the role
+const CLIENT_ID: u8 = 1;
+// frames between the resync requests of a client, like the firmware
+const RESYNC_REQUEST_INTERVAL: u32 = 10;
+
+type Color = (u8, u8, u8);
+
+// same colors as the firmware uses for the led matrix
+const BORDER: Color = (0x00, 0x80, 0x00);
+const EMPTY: Color = (0x00, 0x00, 0x00);
+const PROJECTILE: Color = (0x00, 0x00, 0x80);
+const TARGET: Color = (0xFF, 0x00, 0x00);
+const PLAYER: Color = (0xFF, 0xFF, 0x00);
+const PLAYER_COLORS: [Color; MAX_PLAYERS] = [(0x80, 0x00, 0x80), (0x80, 0x80, 0x00), (0x00, 0x80, 0x80), (0x80, 0x00, 0x00)];
+const PLAYER_NAMES: [&str; MAX_PLAYERS] = ["purple", "olive", "teal", "maroon"];
+
+fn main() -> io::Result<()> {
+    // the rules preset, the computer opponent and the number of players are
+    // optional arguments, like holding a button or the joystick while the
+    // firmware boots
+    let mut rules = GameRules::default();
+    let mut difficulty: Option<Difficulty> = None;
+    let mut player_count = 2;
+    let mut master: Option<Endpoint> = None;
+    let mut client: Option<Endpoint> = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--master" || arg == "--client" {
+            let endpoint = args.next().as_deref().and_then(Endpoint::parse).unwrap_or_else(|| {
+                eprintln!("{} needs an endpoint: pty, listen:PORT, HOST:PORT or a device path", arg);
+                std::process::exit(1)
+            });
+            if arg == "--master" { master = Some(endpoint) } else { client = Some(endpoint) }
+        } else if let Some(preset) = GameRules::from_name(&arg) {
+            rules = preset;
+        } else if let Some(level) = Difficulty::from_name(&arg) {
+            difficulty = Some(level);
+        } else if let Some(count) = arg.parse::<usize>().ok().filter(|c| (2..=MAX_PLAYERS).contains(c)) {
+            player_count = count;
+        } else {
+            eprintln!("unknown argument '{}', use classic, fast, chaotic, easy, normal, hard, 2-{}, --master or --client", arg, MAX_PLAYERS);
+            std::process::exit(1);
+        }
+    }
+    if master.is_some() && (client.is_some() || difficulty.is_some()) {
+        eprintln!("the other players of --master are the clients of the link");
+        std::process::exit(1);
+    }
+
+    // Open the link before the terminal goes raw, a socket can wait for the other end
+    let mut remote = match master.as_ref().or(client.as_ref()) {
+        Some(endpoint) => Some(Remote::open(endpoint, if master.is_some() { 0 } else { CLIENT_ID })?),
+        None => None,
+    };
+    if client.is_some() {
+        return run_client(remote.as_mut().unwrap(), player_count);
+    }
+
+    // the keyboard has room for two players, the computer plays the rest.
+    // the clients of the link play every player after the master
+    let humans = if remote.is_some() { player_count } else if difficulty.is_some() { 1 } else { 2 };
+    let mut computers: Vec<AiController> = (humans..player_count)
+        .map(|i| AiController::new(i, difficulty.unwrap_or(Difficulty::Normal), std::process::id() + i as u32))
+        .collect();
+
+    let _raw = RawTerminal::enable()?;
+    let keys = terminal::spawn_key_reader();
+
+    let mut game_match = Match::new(rules, MAX_SCORE, player_count);
+    let mut link = Link::new();
+    let mut controllers = [
+        KeyboardController::new(PLAYER_1_KEYS, 1),
+        KeyboardController::new(PLAYER_2_KEYS, -1),
+    ];
+    let mut remote_inputs = [ControllerInput::empty(); MAX_PLAYERS];
+    let mut peers = Peers::new(player_count);
+
+    print!("\x1b[2J");
+
+    loop {
+        let frame_start = Instant::now();
+
+        // Collect the keys pressed since the last frame
+        loop {
+            let key = match keys.try_recv() {
+                Ok(k) => k,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+            match key {
+                Key::Char('q') | Key::CtrlC => return Ok(()),
+                Key::Char('r') => game_match = Match::new(rules, MAX_SCORE, player_count),
+                _ => {
+                    for controller in controllers.iter_mut() {
+                        if controller.handle(key) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut inputs = [ControllerInput::empty(); MAX_PLAYERS];
+        inputs[0] = controllers[0].input();
+        if let Some(remote) = remote.as_mut() {
+            // The last input of every accepted client, like read_link of the firmware
+            for frame in remote.receive()? {
+                let player = frame.source as usize;
+                match frame.kind {
+                    link::FrameKind::Hello => {
+                        if let Some(hello) = Hello::decode(frame.payload()) {
+                            let ack = peers.hello(player, &hello);
+                            remote.send(link::FrameKind::HelloAck, &ack.encode())?;
+                        }
+                    }
+                    link::FrameKind::Input if frame.payload().len() == 4 && player >= 1 && player < player_count
+                        && peers.accepted(player) => {
+                        let data = frame.payload();
+                        remote_inputs[player] = ControllerInput::deserialize(&[data[0], data[1], data[2], data[3]]);
+                    }
+                    link::FrameKind::ResyncRequest => link.sender.request_resync(),
+                    _ => {}
+                }
+            }
+            inputs[1..player_count].copy_from_slice(&remote_inputs[1..player_count]);
+        } else {
+            inputs[1] = controllers[1].input();
+        }
+        for computer in computers.iter_mut() {
+            inputs[computer.player()] = computer.input(game_match.state());
+        }
+        game_match.update(inputs);
+
+        let screen = link.send(&Screen::of_match(&game_match), remote.as_mut())?;
+        let mut aiming = [false; MAX_PLAYERS];
+        for (i, player) in game_match.state().players().iter().enumerate() {
+            aiming[i] = player.shoot_btn;
+        }
+        draw(screen, player_count, game_match.max_score(), aiming, &status(&game_match))?;
+
+        let elapsed = frame_start.elapsed();
+        if elapsed < TICK {
+            thread::sleep(TICK - elapsed);
+        }
+    }
+}
+
+// Plays player 1 as a client of a master, draws the screen it streams
+fn run_client(remote: &mut Remote, player_count: usize) -> io::Result<()> {
+    let _raw = RawTerminal::enable()?;
+    let keys = terminal::spawn_key_reader();
+    let mut controller = KeyboardController::new(PLAYER_1_KEYS, 1);
+    let mut receiver = ScreenReceiver::new();
+    let mut answer: Option<Ack> = None;
+    let mut frame: u32 = 0;
+
+    print!("\x1b[2J");
+
+    loop {
+        let frame_start = Instant::now();
+
+        loop {
+            let key = match keys.try_recv() {
+                Ok(k) => k,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            };
+            match key {
+                Key::Char('q') | Key::CtrlC => return Ok(()),
+                _ => {
+                    controller.handle(key);
+                }
+            }
+        }
+
+        for received in remote.receive()? {
+            match received.kind {
+                link::FrameKind::ScreenDelta | link::FrameKind::ScreenResync if received.source == 0 => {
+                    receiver.apply(&received);
+                }
+                // Answer the ping of the master right away
+                link::FrameKind::Ping if received.source == 0 => remote.send(link::FrameKind::Pong, received.payload())?,
+                link::FrameKind::HelloAck if received.source == 0 => {
+                    if let Some(ack) = Ack::decode(received.payload()).filter(|ack| ack.answers(CLIENT_ID, std::process::id())) {
+                        answer = Some(ack);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if frame.is_multiple_of(handshake::HELLO_INTERVAL) {
+            remote.send(link::FrameKind::Hello, &Hello::new(Controller::Keyboard, std::process::id()).encode())?;
+        }
+        let refusal = answer.and_then(|ack| ack.result.err());
+        let input = controller.input();
+        if refusal.is_none() {
+            remote.send(link::FrameKind::Input, &input.serialize())?;
+        }
+        if !receiver.synced() && frame.is_multiple_of(RESYNC_REQUEST_INTERVAL) {
+            remote.send(link::FrameKind::ResyncRequest, &[])?;
+        }
+
+        let status = if let Some(refusal) = refusal {
+            format!("refused by {}: {:?}", remote.port.name, refusal)
+        } else if receiver.synced() {
+            format!("client {} of {}, q to quit", CLIENT_ID, remote.port.name)
+        } else {
+            format!("waiting for the screen of {}", remote.port.name)
+        };
+        let screen = match refusal {
+            Some(_) => Screen::refused(CLIENT_ID as usize),
+            None => *receiver.screen(),
+        };
+        draw(&screen, player_count, MAX_SCORE, [false; MAX_PLAYERS], &status)?;
+
+        frame = frame.wrapping_add(1);
+        let elapsed = frame_start.elapsed();
+        if elapsed < TICK {
+            thread::sleep(TICK - elapsed);
+        }
+    }
+}
+
+// Same colors as shade_color of the firmware
+fn shade_color(shade: Shade) -> Color {
+    match shade {
+        Shade::Empty => EMPTY,
+        Shade::Owner(player) => PLAYER_COLORS[player as usize % MAX_PLAYERS],
+        Shade::Projectile => PROJECTILE,
+        Shade::Target => TARGET,
+        Shade::Player => PLAYER,
+        Shade::Highlight => BORDER,
+    }
+}
+
+// The led matrix colors of the screen inside the green borders
+fn matrix(screen: &Screen) -> [[Color; game::BOARD_WIDTH]; game::BOARD_WIDTH] {
+    let mut matrix = [[BORDER; game::BOARD_WIDTH]; game::BOARD_WIDTH];
+    for (x, y, shade) in screen.cells() {
+        matrix[x][y] = shade_color(shade);
+    }
+    matrix
+}
+
+// Sends the screen of the master through the link encoder and decoder like to a client
+struct Link {
+    sender: ScreenSender,
+    encoder: link::Encoder,
+    decoder: link::Decoder,
+    receiver: ScreenReceiver,
+}
+
+impl Link {
+    fn new() -> Self {
+        Link {
+            sender: ScreenSender::new(),
+            encoder: link::Encoder::new(0),
+            decoder: link::Decoder::new(),
+            receiver: ScreenReceiver::new(),
+        }
+    }
+
+    // the frame also goes to the clients of a remote link
+    fn send(&mut self, screen: &Screen, remote: Option<&mut Remote>) -> io::Result<&Screen> {
+        let mut payload = [0u8; link::MAX_PAYLOAD];
+        let mut bytes = [0u8; link::MAX_FRAME];
+        let (kind, len) = self.sender.next(screen, &mut payload);
+        let len = self.encoder.encode(kind, &payload[..len], &mut bytes).expect("screen frame too long");
+        if let Some(remote) = remote {
+            remote.port.write(&bytes[..len])?;
+        }
+
+        let receiver = &mut self.receiver;
+        self.decoder.feed(&bytes[..len], |result| {
+            if let Ok(frame) = result {
+                receiver.apply(&frame);
+            }
+        });
+        Ok(self.receiver.screen())
+    }
+}
+
+// The uart link to the other boards through an endpoint on the host
+struct Remote {
+    port: Port,
+    incoming: Receiver<Vec<u8>>,
+    encoder: link::Encoder,
+    decoder: link::Decoder,
+}
+
+impl Remote {
+    fn open(endpoint: &Endpoint, source: u8) -> io::Result<Self> {
+        let mut port = endpoint.open()?;
+        let incoming = port.spawn_reader();
+        Ok(Remote { port, incoming, encoder: link::Encoder::new(source), decoder: link::Decoder::new() })
+    }
+
+    // the frames that arrived since the last call
+    fn receive(&mut self) -> io::Result<Vec<link::Frame>> {
+        let mut frames = Vec::new();
+        loop {
+            let bytes = match self.incoming.try_recv() {
+                Ok(bytes) => bytes,
+                Err(TryRecvError::Empty) => return Ok(frames),
+                Err(TryRecvError::Disconnected) => return Err(io::Error::new(io::ErrorKind::BrokenPipe, "the link was closed")),
+            };
+            self.decoder.feed(&bytes, |result| {
+                if let Ok(frame) = result {
+                    frames.push(frame);
+                }
+            });
+        }
+    }
+
+    fn send(&mut self, kind: link::FrameKind, payload: &[u8]) -> io::Result<()> {
+        let mut bytes = [0u8; link::MAX_FRAME];
+        let len = self.encoder.encode(kind, payload, &mut bytes).expect("frame too long");
+        self.port.write(&bytes[..len])
+    }
+}
+
+fn status(game_match: &Match) -> String {
+    match game_match.phase() {
+        Phase::Lobby => String::from("press c (p1) and m (p2) when ready, q to quit"),
+        Phase::Countdown(_) => String::from("get ready..."),
+        Phase::Round => String::from("p1: wasd + space, p2: arrows + enter, r to lobby, q to quit"),
+        Phase::RoundResult(Outcome::Winner(player), _) => format!("{} wins the round!", PLAYER_NAMES[player]),
+        Phase::RoundResult(Outcome::Tie, _) => String::from("tie, the round is played again"),
+        Phase::MatchResult(winner, _) => format!("{} wins the match!", PLAYER_NAMES[winner]),
+    }
+}
+
+fn draw(screen: &Screen, player_count: usize, max_score: u8, aiming: [bool; MAX_PLAYERS], status: &str) -> io::Result<()> {
+    let matrix = matrix(screen);
+    let score = screen.score;
+    let wins = screen.wins;
+
+    // raw mode needs explicit carriage returns
+    let mut frame = String::from("\x1b[H");
+    for y in 0..game::BOARD_WIDTH {
+        for column in matrix.iter() {
+            let (r, g, b) = column[y];
+            let _ = write!(frame, "\x1b[48;2;{};{};{}m  ", r, g, b);
+        }
+        frame.push_str("\x1b[0m\r\n");
+    }
+
+    frame.push_str("\r\n");
+    for i in 0..player_count {
+        let (r, g, b) = PLAYER_COLORS[i];
+        let _ = write!(frame, "\x1b[38;2;{};{};{}m{:>7}\x1b[0m {:3} rounds {}/{} {}\x1b[K\r\n",
+            r, g, b, PLAYER_NAMES[i], score[i], wins[i], max_score,
+            if aiming[i] { "aiming" } else { "" });
+    }
+    let _ = write!(frame, "\r\n{}\x1b[K\r\n", status);
+
+    let mut stdout = io::stdout();
+    stdout.write_all(frame.as_bytes())?;
+    stdout.flush()
+}
@@ -0,0 +1,70 @@
+/*
+ * 	recordings of the bytes that went over a link
+ *
+ * 	a recording is a list of chunks in the order they were
+ * 	read, each with the side it came from and its time:
+ *
+ * 	  side | milliseconds (u32) | length (u16) | bytes
+ *
+ * 	the numbers are little endian. side 0 is the first
+ * 	endpoint of the bridge and 1 the second.
+ */
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::time::Instant;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chunk {
+    pub side: u8,
+    // since the recording started
+    pub millis: u32,
+    pub bytes: Vec<u8>,
+}
+
+pub struct Recorder {
+    out: BufWriter<File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Recorder { out: BufWriter::new(File::create(path)?), start: Instant::now() })
+    }
+
+    pub fn record(&mut self, side: u8, bytes: &[u8]) -> io::Result<()> {
+        let millis = self.start.elapsed().as_millis() as u32;
+        // Longer reads are split, the length has 16 bits
+        for part in bytes.chunks(u16::MAX as usize) {
+            self.out.write_all(&[side])?;
+            self.out.write_all(&millis.to_le_bytes())?;
+            self.out.write_all(&(part.len() as u16).to_le_bytes())?;
+            self.out.write_all(part)?;
+        }
+        // A recording cut short by ctrl-c still has everything up to it
+        self.out.flush()
+    }
+}
+
+pub fn load(path: &str) -> io::Result<Vec<Chunk>> {
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+
+    let mut chunks = Vec::new();
+    let mut i = 0;
+    while i < data.len() {
+        if i + 7 > data.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "recording ends in a chunk header"));
+        }
+        let side = data[i];
+        let millis = u32::from_le_bytes([data[i + 1], data[i + 2], data[i + 3], data[i + 4]]);
+        let len = u16::from_le_bytes([data[i + 5], data[i + 6]]) as usize;
+        i += 7;
+        if i + len > data.len() || side > 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "broken chunk in the recording"));
+        }
+        chunks.push(Chunk { side, millis, bytes: data[i..i + len].to_vec() });
+        i += len;
+    }
+    Ok(chunks)
+}
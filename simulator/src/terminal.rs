@@ -0,0 +1,83 @@
+/*
+ * 	minimal terminal handling for the simulator without
+ * 	extra dependencies: raw mode is toggled with stty and
+ * 	the keys are read from stdin on a separate thread
+ */
+
+use std::io::{self, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    CtrlC,
+}
+
+/// puts the terminal to raw mode, the previous mode is restored on drop
+pub struct RawTerminal {
+    saved_mode: String,
+}
+
+impl RawTerminal {
+    pub fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["raw", "-echo"])?;
+        print!("\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(RawTerminal { saved_mode: saved.trim().to_string() })
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = stty(&[self.saved_mode.as_str()]);
+        print!("\x1b[0m\x1b[?25h\r\n");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// spawns a thread decoding the keys from stdin
+pub fn spawn_key_reader() -> Receiver<Key> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut bytes = stdin.lock().bytes();
+        while let Some(Ok(byte)) = bytes.next() {
+            let key = match byte {
+                3 => Key::CtrlC,
+                b'\r' | b'\n' => Key::Enter,
+                // arrow keys arrive as ESC [ A..D
+                0x1b => match (bytes.next(), bytes.next()) {
+                    (Some(Ok(b'[')), Some(Ok(b'A'))) => Key::Up,
+                    (Some(Ok(b'[')), Some(Ok(b'B'))) => Key::Down,
+                    (Some(Ok(b'[')), Some(Ok(b'C'))) => Key::Right,
+                    (Some(Ok(b'[')), Some(Ok(b'D'))) => Key::Left,
+                    _ => continue,
+                },
+                _ => Key::Char((byte as char).to_ascii_lowercase()),
+            };
+            if sender.send(key).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
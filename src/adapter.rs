@@ -0,0 +1,64 @@
+/*
+ * 	commands for the wifi adapter
+ *
+ * 	a line starting with ESC "BRM " is taken by the adapter
+ * 	instead of being sent to the other boards. it changes the
+ * 	wifi settings, the adapter saves them to its flash and
+ * 	uses them from the next RECONNECT or REBOOT on. the
+ * 	answer is a Reply or Status control frame, see
+ * 	control.rs of the game crate.
+ *
+ * 	SSID text       1 to 31 characters
+ * 	PASS text       8 to 63 characters
+ * 	CHAN n          1 to 13, the channel of the access point
+ * 	PORT n          the port of the connections
+ * 	ROLE MASTER | ROLE CLIENT
+ * 	SEND n | SEND ALL
+ * 	                the connection of the master the next bytes
+ * 	                go to, all of them after a reconnect
+ * 	STATUS          role, ssid, channel, port and connections
+ * 	RECONNECT       sets the wifi up again with the settings
+ * 	REBOOT
+ *
+ * 	the adapter of the master has up to four connections,
+ * 	numbered 0 to 3. it sends a Source event before bytes of
+ * 	another connection than the last ones.
+ */
+
+use core::fmt::{self, Write};
+
+pub const COMMAND_PREFIX: &str = "\x1bBRM ";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Command<'a> {
+    Ssid(&'a str),
+    Password(&'a str),
+    Channel(u8),
+    Port(u16),
+    // true for the master
+    Role(bool),
+    // the connection of the master the next bytes go to, None for all of them
+    Send(Option<u8>),
+    Status,
+    Reconnect,
+    Reboot,
+}
+
+/// writes the command line to the uart of the adapter
+pub fn send<W: Write>(out: &mut W, command: Command) -> fmt::Result {
+    write!(out, "{}", COMMAND_PREFIX)?;
+    match command {
+        Command::Ssid(ssid) => write!(out, "SSID {}", ssid)?,
+        Command::Password(password) => write!(out, "PASS {}", password)?,
+        Command::Channel(channel) => write!(out, "CHAN {}", channel)?,
+        Command::Port(port) => write!(out, "PORT {}", port)?,
+        Command::Role(true) => write!(out, "ROLE MASTER")?,
+        Command::Role(false) => write!(out, "ROLE CLIENT")?,
+        Command::Send(Some(id)) => write!(out, "SEND {}", id)?,
+        Command::Send(None) => write!(out, "SEND ALL")?,
+        Command::Status => write!(out, "STATUS")?,
+        Command::Reconnect => write!(out, "RECONNECT")?,
+        Command::Reboot => write!(out, "REBOOT")?,
+    }
+    write!(out, "\r\n")
+}
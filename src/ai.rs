@@ -0,0 +1,301 @@
+/*
+ * 	computer controlled opponent
+ *
+ * 	produces the same ControllerInput values a nunchuk
+ * 	would, so the engine treats it like any other player.
+ * 	shots are chosen by running the projectile physics for
+ * 	a set of directions and picking the one that lands on
+ * 	the most unpainted and opponent owned cells. when no
+ * 	shot is good enough it walks toward unpainted cells.
+ */
+
+use crate::game;
+use crate::game::{ Board, MovingObject, Vector, Real, Scalar, BOARD_WIDTH, MAXIMUM_OBJECTS };
+use crate::engine::GameState;
+use crate::input::ControllerInput;
+
+const JOY_FULL: i8 = 127;
+
+// the aiming directions as joystick values, 22.5 degrees apart
+const AIM_DIRECTIONS: [(i8, i8); 16] = [
+    (127, 0), (117, 49), (90, 90), (49, 117),
+    (0, 127), (-49, 117), (-90, 90), (-117, 49),
+    (-127, 0), (-117, -49), (-90, -90), (-49, -117),
+    (0, -127), (49, -117), (90, -90), (117, -49),
+];
+
+// upper limit for simulating a shot, in frames
+const MAX_FLIGHT: usize = 255;
+// shots simulated in one frame, the physics runs on soft-float on the
+// gd32 so the directions of a decision are spread over several frames
+const SHOTS_PER_FRAME: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "normal" => Some(Difficulty::Normal),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    // frames to idle between decisions
+    fn think_delay(self) -> u8 {
+        match self {
+            Difficulty::Easy => 8,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 0,
+        }
+    }
+
+    // how many of the AIM_DIRECTIONS are tried for each shot
+    fn directions(self) -> usize {
+        match self {
+            Difficulty::Easy => 2,
+            Difficulty::Normal => 8,
+            Difficulty::Hard => 16,
+        }
+    }
+
+    // frames the trigger is held before shooting
+    fn aim_frames(self) -> u8 {
+        match self {
+            Difficulty::Easy => 6,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 1,
+        }
+    }
+}
+
+pub struct AiController {
+    pub difficulty: Difficulty,
+    // index of the controlled player
+    player: usize,
+    // direction of the shot being aimed
+    aim: (i8, i8),
+    hold: u8,
+    wait: u8,
+    // xorshift state for picking directions
+    seed: u32,
+    // the shot search in progress: first direction, directions tried
+    // so far and the best of them
+    start: usize,
+    tried: usize,
+    best: ((i8, i8), u32),
+}
+
+// public methods
+impl AiController {
+    pub fn new(player: usize, difficulty: Difficulty, seed: u32) -> Self {
+        AiController {
+            difficulty,
+            player,
+            aim: (0, JOY_FULL),
+            hold: 0,
+            wait: 0,
+            // xorshift never leaves zero
+            seed: if seed == 0 { 0x2545_f491 } else { seed },
+            start: 0,
+            tried: 0,
+            best: ((0, JOY_FULL), 0),
+        }
+    }
+
+    // index of the controlled player
+    pub fn player(&self) -> usize {
+        self.player
+    }
+
+    /// the controller state for this frame, call once per frame
+    pub fn input(&mut self, state: &GameState) -> ControllerInput {
+        let player = state.players()[self.player];
+
+        // Nothing the controller does matters while the player waits
+        if player.shoot_timeout > 0 {
+            return self.output((0, 0), false);
+        }
+
+        // The engine is aiming: hold the trigger for a while, then release it to shoot
+        if player.shoot_btn {
+            if self.hold > 0 {
+                self.hold -= 1;
+                return self.output(self.aim, true);
+            }
+            return self.output(self.aim, false);
+        }
+
+        if self.wait > 0 {
+            self.wait -= 1;
+            return self.output((0, 0), false);
+        }
+
+        // Stand still until every direction was tried
+        let (aim, value) = match self.best_shot(state) {
+            Some(best) => best,
+            None => return self.output((0, 0), false),
+        };
+        self.wait = self.difficulty.think_delay();
+        let radius = state.rules().explosion_radius;
+        let area = ((2 * radius + 1) * (2 * radius + 1)) as u32;
+
+        // Shoot when the landing spot repaints at least half of the explosion area,
+        // otherwise look for a better place to shoot from
+        if 2 * value >= area {
+            self.aim = aim;
+            self.hold = self.difficulty.aim_frames();
+            return self.output(aim, true);
+        }
+        match self.walk_direction(state) {
+            Some(step) => self.output(step, false),
+            None => {
+                self.aim = aim;
+                self.hold = self.difficulty.aim_frames();
+                self.output(aim, true)
+            }
+        }
+    }
+}
+
+// private methods
+impl AiController {
+    fn output(&self, joy: (i8, i8), btn_z: bool) -> ControllerInput {
+        ControllerInput {
+            joy_x: joy.0,
+            joy_y: joy.1,
+            btn_z: btn_z as u8,
+            // The C button is always pressed, so the computer is ready in the lobby
+            btn_c: 1,
+            ..ControllerInput::empty()
+        }
+    }
+
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed
+    }
+
+    // tries evenly spread directions starting from a random one, at most
+    // SHOTS_PER_FRAME of them per call. returns the best direction and the
+    // value of its landing spot once all of them were tried
+    fn best_shot(&mut self, state: &GameState) -> Option<((i8, i8), u32)> {
+        let count = self.difficulty.directions();
+        let stride = AIM_DIRECTIONS.len() / count;
+        if self.tried == 0 {
+            self.start = self.random() as usize % AIM_DIRECTIONS.len();
+            self.best = (AIM_DIRECTIONS[self.start], 0);
+        }
+
+        let end = count.min(self.tried + SHOTS_PER_FRAME);
+        for n in self.tried..end {
+            let direction = AIM_DIRECTIONS[(self.start + n * stride) % AIM_DIRECTIONS.len()];
+            let value = match self.landing_spot(state, direction) {
+                Some(pos) => self.area_value(state, pos),
+                None => 0,
+            };
+            if value > self.best.1 {
+                self.best = (direction, value);
+            }
+        }
+        if end < count {
+            self.tried = end;
+            return None;
+        }
+        self.tried = 0;
+        Some(self.best)
+    }
+
+    // runs the physics for a lone projectile shot in the joystick direction,
+    // the same way the engine spawns and explodes them
+    fn landing_spot(&self, state: &GameState, joy: (i8, i8)) -> Option<(usize, usize)> {
+        let player = state.players()[self.player];
+        let rules = state.rules();
+
+        let x_float: f32 = joy.0 as f32;
+        let y_float: f32 = joy.1 as f32;
+        let len: f32 = game::fast_sqrt(game::pow2(game::abs(x_float)) + game::pow2(game::abs(y_float)));
+
+        let position = Vector {
+            x: Real::from_f32(player.x - 1.0f32),
+            y: Real::from_f32(player.y - 1.0f32),
+        };
+        let direction = Vector {
+            x: Real::from_f32((x_float / len) * rules.shot_speed),
+            y: Real::from_f32((y_float / len) * rules.shot_speed),
+        };
+        let deceleration = Real::from_ratio(rules.deceleration.0, rules.deceleration.1);
+
+        let mut objects: [Option<MovingObject>; MAXIMUM_OBJECTS] = [None; MAXIMUM_OBJECTS];
+        objects[0] = Some(MovingObject::new(position, direction, self.player as u8));
+        let mut board: Board<BOARD_WIDTH, BOARD_WIDTH> = Board::new();
+
+        for _ in 0..MAX_FLIGHT {
+            game::game_tick(&mut objects, 1, &mut board, deceleration);
+            let mut object = objects[0]?;
+            let pos = object.position();
+            if !object.moving() || object.get_age() > rules.projectile_lifetime {
+                return Some(pos);
+            }
+            if pos.0 >= 1 && pos.1 >= 1 && pos.0 < BOARD_WIDTH && pos.1 < BOARD_WIDTH {
+                object.add_age();
+                objects[0] = Some(object);
+            }
+        }
+        None
+    }
+
+    // unpainted cells count once, the opponent's twice
+    fn area_value(&self, state: &GameState, pos: (usize, usize)) -> u32 {
+        let radius = state.rules().explosion_radius;
+        let mut value = 0;
+        for x in pos.0.saturating_sub(radius)..=pos.0 + radius {
+            for y in pos.1.saturating_sub(radius)..=pos.1 + radius {
+                if x > 0 && x < (BOARD_WIDTH - 1) && y > 0 && y < (BOARD_WIDTH - 1) {
+                    value += match state.owner(x, y) {
+                        None => 1,
+                        Some(owner) if owner != self.player => 2,
+                        Some(_) => 0,
+                    };
+                }
+            }
+        }
+        value
+    }
+
+    // joystick values for one step toward the nearest unpainted cell,
+    // None if the player is already standing on one or there are none
+    fn walk_direction(&self, state: &GameState) -> Option<(i8, i8)> {
+        let player = state.players()[self.player];
+        let (px, py) = (player.x as i32, player.y as i32);
+
+        let mut nearest: Option<(i32, i32)> = None;
+        let mut nearest_distance = i32::MAX;
+        for y in 1..(BOARD_WIDTH - 1) {
+            for x in 1..(BOARD_WIDTH - 1) {
+                if state.owner(x, y).is_some() {
+                    continue;
+                }
+                let distance = (x as i32 - px).abs() + (y as i32 - py).abs();
+                if distance < nearest_distance {
+                    nearest_distance = distance;
+                    nearest = Some((x as i32, y as i32));
+                }
+            }
+        }
+
+        let (tx, ty) = nearest?;
+        if nearest_distance == 0 {
+            return None;
+        }
+        Some(((tx - px).signum() as i8 * JOY_FULL, (ty - py).signum() as i8 * JOY_FULL))
+    }
+}
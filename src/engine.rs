@@ -0,0 +1,535 @@
+/*
+ * 	hardware independent game engine
+ *
+ * 	owns the players, the projectiles and the painted
+ * 	territory. the firmware only feeds the controller
+ * 	inputs in with step() once per frame and renders the
+ * 	resulting state to the led matrix.
+ */
+
+use crate::game;
+use crate::game::{ Board, Cell, MovingObject, Vector, Real, Scalar, BOARD_WIDTH, MAXIMUM_OBJECTS };
+use crate::input::ControllerInput;
+use crate::rules::GameRules;
+
+pub const MAX_PLAYERS: usize = 4;
+
+// starting positions, one side of the board for each player
+const START_POSITIONS: [(f32, f32); MAX_PLAYERS] = [(7.0, 1.0), (8.0, 14.0), (1.0, 8.0), (14.0, 7.0)];
+
+// cells inside the borders that can be painted
+pub const PLAYABLE_CELLS: usize = (BOARD_WIDTH - 2) * (BOARD_WIDTH - 2);
+
+#[derive(Clone, Copy)]
+pub struct Player {
+    pub x: f32,
+    pub y: f32,
+    pub shoot_timeout: u8,
+    pub shoot_btn: bool,
+    pub target_x: usize,
+    pub target_y: usize,
+    pub use_target: bool,
+    pub input: ControllerInput,
+}
+
+impl Default for Player {
+    fn default() -> Self {
+        Player {
+            x: f32::default(),
+            y: f32::default(),
+            shoot_timeout: 4,
+            shoot_btn: false,
+            target_x: 0,
+            target_y: 0,
+            use_target: false,
+            input: ControllerInput::empty(),
+        }
+    }
+}
+
+impl Player {
+    pub fn new(x: f32, y: f32) -> Self {
+        Player { x, y, ..Default::default() }
+    }
+
+    // the aim indicator position, if the player is currently aiming
+    pub fn target(&self) -> Option<(usize, usize)> {
+        if self.use_target {
+            Some((self.target_x, self.target_y))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Outcome {
+    Winner(usize),
+    Tie,
+}
+
+/// what happened during a single step, for the caller to react to
+#[derive(Clone, Copy)]
+pub struct Events {
+    pub fired: [bool; MAX_PLAYERS],
+    pub explosions: usize,
+    // projectiles bouncing off the walls
+    pub bounces: usize,
+    pub score: [u8; MAX_PLAYERS],
+    pub outcome: Option<Outcome>,
+}
+
+impl Events {
+    fn empty() -> Self {
+        Events {
+            fired: [false; MAX_PLAYERS],
+            explosions: 0,
+            bounces: 0,
+            score: [0; MAX_PLAYERS],
+            outcome: None,
+        }
+    }
+}
+
+pub struct GameState {
+    players: [Player; MAX_PLAYERS],
+    // players taking part, the rest of the array is unused
+    player_count: usize,
+    objects: [Option<MovingObject>; MAXIMUM_OBJECTS],
+    number_of_objects: usize,
+    board: Board<BOARD_WIDTH, BOARD_WIDTH>,
+    // index of the player owning each cell, None when unpainted
+    territory: [[Option<usize>; BOARD_WIDTH]; BOARD_WIDTH],
+    rules: GameRules,
+}
+
+impl GameState {
+    /// player_count is clamped to 2..=MAX_PLAYERS
+    pub fn new(rules: GameRules, player_count: usize) -> Self {
+        let mut players = [Player::default(); MAX_PLAYERS];
+        for (player, start) in players.iter_mut().zip(START_POSITIONS.iter()) {
+            *player = Player::new(start.0, start.1);
+        }
+
+        GameState {
+            players,
+            player_count: player_count.clamp(2, MAX_PLAYERS),
+            objects: [None; MAXIMUM_OBJECTS],
+            number_of_objects: 0,
+            board: Board::new(),
+            territory: [[None; BOARD_WIDTH]; BOARD_WIDTH],
+            rules,
+        }
+    }
+
+    pub fn rules(&self) -> &GameRules {
+        &self.rules
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players[..self.player_count]
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.player_count
+    }
+
+    // the projectiles and wall hits of the last step
+    pub fn board(&self) -> &Board<BOARD_WIDTH, BOARD_WIDTH> {
+        &self.board
+    }
+
+    // owner of the cell at x, y
+    pub fn owner(&self, x: usize, y: usize) -> Option<usize> {
+        if x >= BOARD_WIDTH || y >= BOARD_WIDTH {
+            return None;
+        }
+        self.territory[x][y]
+    }
+
+    // positions of the projectiles still in flight, for rendering
+    pub fn projectiles(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.objects[..self.number_of_objects]
+            .iter()
+            .filter_map(|o| *o)
+            .map(|o| o.position())
+            .filter(|pos| pos.0 > 0 && pos.1 > 0 && pos.0 < BOARD_WIDTH - 1 && pos.1 < BOARD_WIDTH - 1)
+    }
+
+    /// hash of everything the next steps depend on, equal states have equal checksums
+    pub fn checksum(&self) -> u32 {
+        let mut hash = Checksum::new();
+        for player in self.players().iter() {
+            hash.add(&player.x.to_bits().to_le_bytes());
+            hash.add(&player.y.to_bits().to_le_bytes());
+            hash.add(&[player.shoot_timeout, player.shoot_btn as u8, player.use_target as u8,
+                player.target_x as u8, player.target_y as u8]);
+        }
+        for object in self.objects[..self.number_of_objects].iter() {
+            match object {
+                Some(o) => {
+                    let (location, velocity) = (o.location(), o.velocity());
+                    for value in [location.x, location.y, velocity.x, velocity.y].iter() {
+                        hash.add(&value.to_f32().to_bits().to_le_bytes());
+                    }
+                    hash.add(&[o.owner, o.get_age() as u8]);
+                }
+                None => hash.add(&[0xFF]),
+            }
+        }
+        for column in self.territory.iter() {
+            for cell in column.iter() {
+                hash.add(&[cell.map(|owner| owner as u8).unwrap_or(0xFF)]);
+            }
+        }
+        hash.value()
+    }
+
+    pub fn score(&self) -> [u8; MAX_PLAYERS] {
+        let mut score = [0u8; MAX_PLAYERS];
+        for y in 1..(BOARD_WIDTH - 1) {
+            for x in 1..(BOARD_WIDTH - 1) {
+                if let Some(owner) = self.territory[x][y] {
+                    score[owner] += 1;
+                }
+            }
+        }
+        score
+    }
+
+    /// advances the match by one frame using the given controller inputs,
+    /// the inputs of the slots over player_count are ignored
+    pub fn step(&mut self, inputs: [ControllerInput; MAX_PLAYERS]) -> Events {
+        let mut events = Events::empty();
+
+        for (player, input) in self.players.iter_mut().zip(inputs.iter()).take(self.player_count) {
+            player.input = *input;
+        }
+
+        // Apply the game physics
+        if self.number_of_objects > 0 {
+            let deceleration = Real::from_ratio(self.rules.deceleration.0, self.rules.deceleration.1);
+            let collisions = game::game_tick(&mut self.objects, self.number_of_objects, &mut self.board, deceleration);
+            events.bounces = collisions.iter().filter(|c| c.target.is_wall()).count();
+        }
+        self.board.clear();
+
+        for i in 0..self.number_of_objects {
+            let mut object = match self.objects[i] {
+                Some(o) => o,
+                None => continue,
+            };
+            let pos = object.position();
+
+            // "Explode" objects that have stopped
+            if !object.moving() || object.get_age() > self.rules.projectile_lifetime {
+                self.paint_area(pos, self.rules.explosion_radius, object.owner as usize);
+                self.objects[i] = None;
+                events.explosions += 1;
+            } else if pos.0 < 1 || pos.1 < 1 || pos.0 > BOARD_WIDTH - 1 || pos.1 > BOARD_WIDTH - 1 {
+                // ToDo: do something with out-of-bounds object
+            } else {
+                let _ = self.board.set(pos.0, pos.1, Cell::Projectile(object.owner));
+                object.add_age();
+                self.objects[i] = Some(object);
+            }
+        }
+
+        for i in 0..self.player_count {
+            events.fired[i] = self.update_player(i);
+        }
+
+        // Paint a trail after the projectiles using the shooting player color
+        for i in 0..self.number_of_objects {
+            let object = match self.objects[i] {
+                Some(o) => o,
+                None => continue,
+            };
+            let pos = object.position();
+            if pos.0 < BOARD_WIDTH && pos.1 < BOARD_WIDTH {
+                self.territory[pos.0][pos.1] = Some(object.owner as usize);
+            }
+        }
+
+        events.score = self.score();
+
+        // Ending condition, the players' colors cover the whole board
+        let total: usize = events.score.iter().map(|s| *s as usize).sum();
+        if total == PLAYABLE_CELLS {
+            events.outcome = Some(outcome_of(&events.score[..self.player_count]));
+        }
+
+        events
+    }
+}
+
+// private methods
+impl GameState {
+    // paints a square of cells around pos, leaving the borders untouched
+    fn paint_area(&mut self, pos: (usize, usize), radius: usize, owner: usize) {
+        for x in pos.0.saturating_sub(radius)..=pos.0 + radius {
+            for y in pos.1.saturating_sub(radius)..=pos.1 + radius {
+                if x > 0 && x < (BOARD_WIDTH - 1) && y > 0 && y < (BOARD_WIDTH - 1) {
+                    self.territory[x][y] = Some(owner);
+                }
+            }
+        }
+    }
+
+    // handles moving, aiming and shooting for one player,
+    // returns true if the player fired a projectile
+    fn update_player(&mut self, i: usize) -> bool {
+        let input = self.players[i].input;
+        let mut fired = false;
+
+        // If we are waiting after shooting or moving
+        if self.players[i].shoot_timeout > 0 {
+            self.players[i].shoot_timeout -= 1;
+        } else if self.players[i].shoot_btn { // Trigger is pressed down, update the targeting indicator
+
+            // Calculate target direction
+            let x_float: f32 = input.joy_x as f32;
+            let y_float: f32 = input.joy_y as f32;
+            let len: f32 = game::fast_sqrt(game::pow2(game::abs(x_float)) + game::pow2(game::abs(y_float)));
+
+            // Shoot when player button was pressed and now released
+            if input.btn_z == 0 {
+                self.players[i].shoot_timeout = self.rules.shoot_timeout;
+                self.players[i].shoot_btn = false;
+                self.players[i].use_target = false;
+
+                // Add offset to the projectile to compensate the game logic
+                let position = Vector {
+                    x: Real::from_f32(self.players[i].x - 1.0f32),
+                    y: Real::from_f32(self.players[i].y - 1.0f32),
+                };
+                let direction = Vector {
+                    x: Real::from_f32((x_float / len) * self.rules.shot_speed),
+                    y: Real::from_f32((y_float / len) * self.rules.shot_speed),
+                };
+                fired = self.spawn_projectile(position, direction, i as u8);
+            } else { // Update the target vector if player still holding the trigger
+                let xpos = (self.players[i].x + (x_float / len) * self.rules.aim_distance) as i32;
+                let ypos = (self.players[i].y + (y_float / len) * self.rules.aim_distance) as i32;
+
+                // Make sure the target is not outside the boundaries
+                if xpos > 0 && ypos > 0 && xpos < (BOARD_WIDTH - 1) as i32 && ypos < (BOARD_WIDTH - 1) as i32 {
+                    self.players[i].target_x = xpos as usize;
+                    self.players[i].target_y = ypos as usize;
+                    self.players[i].use_target = true;
+                }
+            }
+        } else { // If player is not waiting and not pressing the trigger yet
+            if input.btn_z == 1 {
+                self.players[i].shoot_btn = true;
+            } else {
+                self.move_player(i, input);
+            }
+        }
+
+        fired
+    }
+
+    // Move player when joystick (range -128..127) over the threshold of the rules
+    fn move_player(&mut self, i: usize, input: ControllerInput) {
+        let threshold = self.rules.joystick_threshold;
+        let mut x_dir: i8 = 0;
+        let mut y_dir: i8 = 0;
+
+        // Check if moving on X-axis
+        if input.joy_x > threshold {
+            x_dir = 1;
+        } else if input.joy_x < -threshold {
+            x_dir = -1;
+        }
+
+        // Check if moving on Y-axis
+        if input.joy_y > threshold {
+            y_dir = 1;
+        } else if input.joy_y < -threshold {
+            y_dir = -1;
+        }
+
+        if x_dir == 0 && y_dir == 0 {
+            return;
+        }
+
+        // If we are moving make sure we are not over the boundaries in any direction
+        let player = &mut self.players[i];
+        if (player.x as i8) + x_dir > 0 && (player.x as i8) + x_dir < (BOARD_WIDTH - 1) as i8 {
+            player.x += x_dir as f32;
+        }
+        if (player.y as i8) + y_dir > 0 && (player.y as i8) + y_dir < (BOARD_WIDTH - 1) as i8 {
+            player.y += y_dir as f32;
+        }
+
+        // Add "shoot timeout" also after moving
+        player.shoot_timeout = self.rules.move_timeout;
+    }
+
+    // Iterate through the existing object list to find first empty space to push new
+    fn spawn_projectile(&mut self, position: Vector, direction: Vector, owner: u8) -> bool {
+        for ii in 0..(MAXIMUM_OBJECTS - 1) {
+            if self.objects[ii].is_none() {
+                self.objects[ii] = Some(MovingObject::new(position, direction, owner));
+                if ii + 1 > self.number_of_objects {
+                    self.number_of_objects = ii + 1;
+                }
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// FNV-1a, small enough for the device and good enough to notice a desync
+pub struct Checksum(u32);
+
+impl Checksum {
+    pub fn new() -> Self {
+        Checksum(0x811C_9DC5)
+    }
+
+    pub fn add(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u32;
+            self.0 = self.0.wrapping_mul(0x0100_0193);
+        }
+    }
+
+    pub fn value(&self) -> u32 {
+        self.0
+    }
+}
+
+impl Default for Checksum {
+    fn default() -> Self {
+        Checksum::new()
+    }
+}
+
+// the player with the most cells wins, a shared best score is a tie
+fn outcome_of(score: &[u8]) -> Outcome {
+    let best = score.iter().copied().max().unwrap_or(0);
+    let mut leaders = score.iter().enumerate().filter(|(_, s)| **s == best);
+
+    match (leaders.next(), leaders.next()) {
+        (Some((player, _)), None) => Outcome::Winner(player),
+        _ => Outcome::Tie,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(joy_x: i8, joy_y: i8, btn_z: u8) -> [ControllerInput; MAX_PLAYERS] {
+        let mut inputs = [ControllerInput::empty(); MAX_PLAYERS];
+        inputs[0].joy_x = joy_x;
+        inputs[0].joy_y = joy_y;
+        inputs[0].btn_z = btn_z;
+        inputs
+    }
+
+    // steps with nothing pressed until player 0 can move or shoot again
+    fn settle(state: &mut GameState) {
+        while state.players()[0].shoot_timeout > 0 {
+            state.step(input(0, 0, 0));
+        }
+    }
+
+    #[test]
+    fn player_count_is_clamped() {
+        assert_eq!(GameState::new(GameRules::classic(), 0).player_count(), 2);
+        assert_eq!(GameState::new(GameRules::classic(), 3).player_count(), 3);
+        assert_eq!(GameState::new(GameRules::classic(), 9).player_count(), MAX_PLAYERS);
+        assert_eq!(GameState::new(GameRules::classic(), 3).players().len(), 3);
+    }
+
+    #[test]
+    fn moves_one_cell_and_waits() {
+        let rules = GameRules::classic();
+        let mut state = GameState::new(rules, 2);
+        settle(&mut state);
+        let (x, y) = (state.players()[0].x, state.players()[0].y);
+
+        state.step(input(127, 0, 0));
+        assert_eq!((state.players()[0].x, state.players()[0].y), (x + 1.0, y));
+        assert_eq!(state.players()[0].shoot_timeout, rules.move_timeout);
+
+        // Held joystick does nothing until the timeout is over
+        state.step(input(127, 0, 0));
+        assert_eq!(state.players()[0].x, x + 1.0);
+
+        // A deflection under the threshold is not a move
+        settle(&mut state);
+        state.step(input(rules.joystick_threshold, 0, 0));
+        assert_eq!(state.players()[0].x, x + 1.0);
+    }
+
+    #[test]
+    fn stays_inside_the_borders() {
+        let mut state = GameState::new(GameRules::classic(), 2);
+        for _ in 0..BOARD_WIDTH * 8 {
+            state.step(input(-127, -127, 0));
+        }
+        assert_eq!((state.players()[0].x, state.players()[0].y), (1.0, 1.0));
+    }
+
+    #[test]
+    fn shot_paints_the_territory_of_the_shooter() {
+        let rules = GameRules::classic();
+        let mut state = GameState::new(rules, 2);
+        settle(&mut state);
+
+        // Press, aim down and release
+        state.step(input(0, 0, 1));
+        state.step(input(0, 127, 1));
+        assert!(state.players()[0].target().is_some());
+        let events = state.step(input(0, 127, 0));
+        assert!(events.fired[0] && !events.fired[1]);
+        assert_eq!(state.players()[0].shoot_timeout, rules.shoot_timeout);
+        assert!(state.players()[0].target().is_none());
+
+        let mut explosions = 0;
+        let mut score = [0; MAX_PLAYERS];
+        for _ in 0..rules.projectile_lifetime + 2 {
+            let events = state.step(input(0, 0, 0));
+            explosions += events.explosions;
+            score = events.score;
+        }
+        assert_eq!(explosions, 1);
+        assert_eq!(state.projectiles().count(), 0);
+        // At least the explosion, the trail can add more
+        let radius = rules.explosion_radius * 2 + 1;
+        assert!(score[0] as usize >= radius * radius / 2);
+        assert_eq!(score[1], 0);
+        assert_eq!(score, state.score());
+    }
+
+    #[test]
+    fn checksum_follows_the_state() {
+        let mut a = GameState::new(GameRules::classic(), 2);
+        let mut b = GameState::new(GameRules::classic(), 2);
+        assert_eq!(a.checksum(), b.checksum());
+
+        a.step(input(127, 0, 0));
+        b.step(input(127, 0, 0));
+        assert_eq!(a.checksum(), b.checksum());
+
+        settle(&mut a);
+        settle(&mut b);
+        let before = a.checksum();
+        a.step(input(0, 127, 0));
+        b.step(input(0, 0, 0));
+        assert_ne!(a.checksum(), before);
+        assert_ne!(a.checksum(), b.checksum());
+    }
+
+    #[test]
+    fn most_cells_win() {
+        assert!(outcome_of(&[10, 3]) == Outcome::Winner(0));
+        assert!(outcome_of(&[1, 2, 9, 4]) == Outcome::Winner(2));
+        assert!(outcome_of(&[5, 5, 1]) == Outcome::Tie);
+    }
+}
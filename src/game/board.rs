@@ -0,0 +1,119 @@
+/*
+ * 	grid of cells the physics writes the projectiles to
+ *
+ * 	the outermost cells are the walls, projectiles bouncing
+ * 	off a wall mark the wall cell with their owner.
+ */
+
+use core::fmt;
+
+// symbols used when printing the board, indexed by the owner
+const OWNER_SYMBOLS: [char; 4] = ['*', '#', '+', '@'];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Cell {
+    Empty,
+    Wall,
+    /// projectile shot by the player with this index
+    Projectile(u8),
+    /// wall cell hit by a projectile of the player with this index
+    WallHit(u8),
+}
+
+impl Cell {
+    pub fn owner(self) -> Option<u8> {
+        match self {
+            Cell::Projectile(owner) | Cell::WallHit(owner) => Some(owner),
+            _ => None,
+        }
+    }
+
+    pub fn symbol(self) -> char {
+        match self {
+            Cell::Empty => '.',
+            Cell::Wall => 'X',
+            Cell::Projectile(owner) | Cell::WallHit(owner) => {
+                *OWNER_SYMBOLS.get(owner as usize).unwrap_or(&'?')
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct OutOfBounds;
+
+#[derive(Copy, Clone)]
+pub struct Board<const W: usize, const H: usize> {
+    cells: [[Cell; W]; H],
+}
+
+impl<const W: usize, const H: usize> Board<W, H> {
+    /// walls around an empty inside
+    pub fn new() -> Self {
+        let mut board = Board { cells: [[Cell::Wall; W]; H] };
+        board.clear();
+        board
+    }
+
+    pub fn width(&self) -> usize {
+        W
+    }
+
+    pub fn height(&self) -> usize {
+        H
+    }
+
+    /// true for the cells inside the walls
+    pub fn is_inside(&self, x: usize, y: usize) -> bool {
+        x > 0 && y > 0 && x < W - 1 && y < H - 1
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<Cell> {
+        if x >= W || y >= H {
+            return None;
+        }
+        Some(self.cells[y][x])
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) -> Result<(), OutOfBounds> {
+        if x >= W || y >= H {
+            return Err(OutOfBounds);
+        }
+        self.cells[y][x] = cell;
+        Ok(())
+    }
+
+    /// empties the inside, the walls keep their hits
+    pub fn clear(&mut self) {
+        for y in 1..(H - 1) {
+            for x in 1..(W - 1) {
+                self.cells[y][x] = Cell::Empty;
+            }
+        }
+    }
+
+    /// all cells row by row as (x, y, cell)
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, Cell)> + '_ {
+        self.cells.iter().enumerate().flat_map(|(y, row)| {
+            row.iter().enumerate().map(move |(x, cell)| (x, y, *cell))
+        })
+    }
+}
+
+impl<const W: usize, const H: usize> Default for Board<W, H> {
+    fn default() -> Self {
+        Board::new()
+    }
+}
+
+impl<const W: usize, const H: usize> fmt::Display for Board<W, H> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.cells.iter() {
+            for cell in row.iter() {
+                write!(f, "{} ", cell.symbol())?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
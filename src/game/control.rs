@@ -0,0 +1,179 @@
+/*
+ * 	control frames of the wifi adapter
+ *
+ * 	the adapter tells its board what happens to the wifi and
+ * 	the connections in link frames of the Adapter kind, sent
+ * 	with the ADAPTER_SOURCE id. they only go to the board
+ * 	wired to the adapter, never over the wifi, so nothing but
+ * 	frames reaches the uart of the board. the first byte of
+ * 	the payload is the event, the rest its arguments:
+ *
+ * 	  Boot      the adapter started and waits for the role
+ * 	  Wifi      state
+ * 	  LinkUp    connection id
+ * 	  LinkDown  connection id
+ * 	  Source    connection id, the frames after it came from it
+ * 	  Rssi      signal strength in dBm (i8)
+ * 	  Error     code, value
+ * 	  Reply     1 for OK, 0 for ERR, the answer to a command
+ * 	  Status    role, channel, port (u16), links, ssid
+ *
+ * 	the commands to the adapter are lines, see adapter.rs of
+ * 	the firmware.
+ */
+
+use crate::link::MAX_PAYLOAD;
+
+const BOOT: u8 = 0x01;
+const WIFI: u8 = 0x02;
+const LINK_UP: u8 = 0x03;
+const LINK_DOWN: u8 = 0x04;
+const SOURCE: u8 = 0x05;
+const RSSI: u8 = 0x06;
+const ERROR: u8 = 0x07;
+const REPLY: u8 = 0x08;
+const STATUS: u8 = 0x09;
+
+// role, channel, port and links before the ssid
+const STATUS_LEN: usize = 6;
+
+/// the state of the wifi, the numbers up to Connected are the station states of the sdk
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WifiState {
+    Idle = 0,
+    Connecting = 1,
+    WrongPassword = 2,
+    NoAccessPoint = 3,
+    Failed = 4,
+    Connected = 5,
+    // the access point of the master is up
+    AccessPoint = 6,
+}
+
+impl WifiState {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(WifiState::Idle),
+            1 => Some(WifiState::Connecting),
+            2 => Some(WifiState::WrongPassword),
+            3 => Some(WifiState::NoAccessPoint),
+            4 => Some(WifiState::Failed),
+            5 => Some(WifiState::Connected),
+            6 => Some(WifiState::AccessPoint),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum AdapterError {
+    // the sdk refused the wifi settings, the value is the step that failed
+    Wifi = 1,
+    // a fifth connection to the master was closed
+    TooManyConns = 2,
+    Panic = 3,
+}
+
+impl AdapterError {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(AdapterError::Wifi),
+            2 => Some(AdapterError::TooManyConns),
+            3 => Some(AdapterError::Panic),
+            _ => None,
+        }
+    }
+}
+
+/// the connections the adapter of the master keeps, the soft-AP takes four stations: the
+/// clients and the spectators. the ids of the events are below it
+pub const MAX_CONNS: usize = 4;
+
+/// the role the adapter was told, 0 before it was
+pub const ROLE_UNKNOWN: u8 = 0;
+pub const ROLE_CLIENT: u8 = 1;
+pub const ROLE_MASTER: u8 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Status<'a> {
+    pub role: u8,
+    pub channel: u8,
+    pub port: u16,
+    // connections to the other boards
+    pub links: u8,
+    pub ssid: &'a str,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Event<'a> {
+    Boot,
+    Wifi(WifiState),
+    LinkUp(u8),
+    LinkDown(u8),
+    Source(u8),
+    Rssi(i8),
+    Error(AdapterError, u8),
+    Reply(bool),
+    Status(Status<'a>),
+}
+
+impl<'a> Event<'a> {
+    /// writes the payload of the frame to out, returns its length
+    pub fn encode(&self, out: &mut [u8; MAX_PAYLOAD]) -> usize {
+        match *self {
+            Event::Boot => {
+                out[0] = BOOT;
+                1
+            }
+            Event::Wifi(state) => pair(out, WIFI, state as u8),
+            Event::LinkUp(id) => pair(out, LINK_UP, id),
+            Event::LinkDown(id) => pair(out, LINK_DOWN, id),
+            Event::Source(id) => pair(out, SOURCE, id),
+            Event::Rssi(rssi) => pair(out, RSSI, rssi as u8),
+            Event::Error(error, value) => {
+                out[0] = ERROR;
+                out[1] = error as u8;
+                out[2] = value;
+                3
+            }
+            Event::Reply(ok) => pair(out, REPLY, ok as u8),
+            Event::Status(status) => {
+                let port = status.port.to_le_bytes();
+                out[..STATUS_LEN].copy_from_slice(&[STATUS, status.role, status.channel, port[0], port[1], status.links]);
+                // A longer ssid than the payload takes is cut, the adapter keeps 31 characters
+                let ssid = status.ssid.as_bytes();
+                let len = ssid.len().min(MAX_PAYLOAD - STATUS_LEN);
+                out[STATUS_LEN..STATUS_LEN + len].copy_from_slice(&ssid[..len]);
+                STATUS_LEN + len
+            }
+        }
+    }
+
+    pub fn decode(bytes: &'a [u8]) -> Option<Self> {
+        let arg = bytes.get(1).copied();
+        match (*bytes.first()?, bytes.len()) {
+            (BOOT, 1) => Some(Event::Boot),
+            (WIFI, 2) => WifiState::from_u8(arg?).map(Event::Wifi),
+            (LINK_UP, 2) => Some(Event::LinkUp(arg?)),
+            (LINK_DOWN, 2) => Some(Event::LinkDown(arg?)),
+            (SOURCE, 2) => Some(Event::Source(arg?)),
+            (RSSI, 2) => Some(Event::Rssi(arg? as i8)),
+            (ERROR, 3) => AdapterError::from_u8(arg?).map(|error| Event::Error(error, bytes[2])),
+            (REPLY, 2) => Some(Event::Reply(arg? != 0)),
+            (STATUS, len) if len >= STATUS_LEN => Some(Event::Status(Status {
+                role: bytes[1],
+                channel: bytes[2],
+                port: u16::from_le_bytes([bytes[3], bytes[4]]),
+                links: bytes[5],
+                ssid: core::str::from_utf8(&bytes[STATUS_LEN..]).ok()?,
+            })),
+            _ => None,
+        }
+    }
+}
+
+fn pair(out: &mut [u8; MAX_PAYLOAD], event: u8, value: u8) -> usize {
+    out[0] = event;
+    out[1] = value;
+    2
+}
@@ -0,0 +1,222 @@
+/*
+ * 	Q16.16 fixed point number
+ *
+ * 	16 integer bits and 16 fractional bits in an i32. the
+ * 	board is 16 cells wide and the velocities stay below a
+ * 	few cells per tick, so the range is plenty. products and
+ * 	quotients go through i64 and saturate instead of wrapping.
+ */
+
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::scalar::Scalar;
+
+const FRACTION_BITS: u32 = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, PartialOrd, Eq, Ord)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    pub const MAX: Fixed = Fixed(i32::MAX);
+    pub const MIN: Fixed = Fixed(i32::MIN);
+
+    pub const fn from_bits(bits: i32) -> Self {
+        Fixed(bits)
+    }
+
+    pub const fn to_bits(self) -> i32 {
+        self.0
+    }
+
+    fn saturate(value: i64) -> Self {
+        if value > i32::MAX as i64 {
+            Fixed::MAX
+        } else if value < i32::MIN as i64 {
+            Fixed::MIN
+        } else {
+            Fixed(value as i32)
+        }
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed::saturate((self.0 as i64 * other.0 as i64) >> FRACTION_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+    // division by zero saturates, like f32 goes to infinity
+    fn div(self, other: Fixed) -> Fixed {
+        if other.0 == 0 {
+            return if self.0 < 0 { Fixed::MIN } else { Fixed::MAX };
+        }
+        Fixed::saturate(((self.0 as i64) << FRACTION_BITS) / other.0 as i64)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+impl Scalar for Fixed {
+    const ZERO: Self = Fixed(0);
+    const ONE: Self = Fixed(1 << FRACTION_BITS);
+    const EPSILON: Self = Fixed(1);
+
+    fn from_int(num: i32) -> Self {
+        Fixed::saturate((num as i64) << FRACTION_BITS)
+    }
+
+    fn from_ratio(num: i32, den: i32) -> Self {
+        Fixed::from_int(num) / Fixed::from_int(den)
+    }
+
+    fn from_f32(num: f32) -> Self {
+        Fixed::saturate((num * (1u32 << FRACTION_BITS) as f32) as i64)
+    }
+
+    fn to_f32(self) -> f32 {
+        self.0 as f32 / (1u32 << FRACTION_BITS) as f32
+    }
+
+    fn to_usize(self) -> usize {
+        if self.0 < 0 {
+            0
+        } else {
+            (self.0 >> FRACTION_BITS) as usize
+        }
+    }
+
+    fn abs(self) -> Self {
+        Fixed(self.0.saturating_abs())
+    }
+
+    fn sqrt(self) -> Self {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        // sqrt(x * 2^16) * 2^8 = sqrt(x) * 2^16
+        Fixed(isqrt((self.0 as u64) << FRACTION_BITS) as i32)
+    }
+
+    // b*b and 4*a*c do not fit Q16.16 when the objects are far apart,
+    // so the determinant is calculated in Q32.32
+    fn solve_quadratic(a: Self, b: Self, c: Self) -> Self {
+        let b2 = (b.0 as i64).saturating_mul(b.0 as i64);
+        let ac4 = (a.0 as i64).saturating_mul(c.0 as i64).saturating_mul(4);
+        let determinant = b2.saturating_sub(ac4);
+        if determinant < 0 {
+            return -Fixed::ONE;
+        }
+        let root = Fixed::saturate(isqrt(determinant as u64) as i64);
+        (-b - root) / (Fixed::from_int(2) * a)
+    }
+}
+
+// integer square root, rounded down
+fn isqrt(num: u64) -> u64 {
+    if num < 2 {
+        return num;
+    }
+    let mut result: u64 = 0;
+    let mut bit: u64 = 1 << 62;
+    while bit > num {
+        bit >>= 2;
+    }
+    let mut rest = num;
+    while bit != 0 {
+        if rest >= result + bit {
+            rest -= result + bit;
+            result = (result >> 1) + bit;
+        } else {
+            result >>= 1;
+        }
+        bit >>= 2;
+    }
+    result
+}
+
+// The same projectile scenarios with the f32 and the Q16.16 physics, the trajectories
+// may not drift apart more than TOLERANCE cells
+#[cfg(test)]
+mod tests {
+    use super::Fixed;
+    use crate::{game_tick, Board, MovingObject, Scalar, Vector, BOARD_WIDTH, DECELERATION, MAXIMUM_OBJECTS};
+
+    // allowed difference in cells between the two trajectories, the f32
+    // path uses the approximate fast_sqrt so they are never identical
+    const TOLERANCE: f32 = 0.5;
+    const TICKS: usize = 60;
+
+    // (x, y, velocity x, velocity y), the same launches the prototype uses
+    // plus single shots like the players fire them
+    const SCENARIOS: [&[(f32, f32, f32, f32)]; 5] = [
+        &[(6.5, 6.5, 3.536, -3.536), (6.5, 6.5, -2.868, -4.096)],
+        &[(6.5, 6.5, -1.710, 4.698), (6.5, 6.5, -4.728, 1.628), (6.5, 6.5, 0.436, 4.981)],
+        &[(6.0, 0.0, 0.0, 2.0), (6.5, 13.0, 0.0, -2.0)],
+        &[(6.0, 0.0, 1.414, 1.414), (7.0, 13.0, -1.789, -0.894)],
+        &[(1.0, 1.0, 2.0, 0.0), (12.0, 1.0, -2.0, 0.0), (1.0, 12.0, 0.0, -2.0), (12.0, 12.0, -1.414, -1.414)],
+    ];
+
+    fn spawn<S: Scalar>(launches: &[(f32, f32, f32, f32)]) -> [Option<MovingObject<S>>; MAXIMUM_OBJECTS] {
+        let mut objects: [Option<MovingObject<S>>; MAXIMUM_OBJECTS] = [None; MAXIMUM_OBJECTS];
+        for (i, (x, y, vx, vy)) in launches.iter().enumerate() {
+            objects[i] = Some(MovingObject::new(
+                Vector { x: S::from_f32(*x), y: S::from_f32(*y) },
+                Vector { x: S::from_f32(*vx), y: S::from_f32(*vy) },
+                0));
+        }
+        objects
+    }
+
+    fn compare(launches: &[(f32, f32, f32, f32)]) {
+        let float_deceleration = f32::from_ratio(DECELERATION.0, DECELERATION.1);
+        let fixed_deceleration = Fixed::from_ratio(DECELERATION.0, DECELERATION.1);
+        let mut float_objects = spawn::<f32>(launches);
+        let mut fixed_objects = spawn::<Fixed>(launches);
+        let mut float_board: Board<BOARD_WIDTH, BOARD_WIDTH> = Board::new();
+        let mut fixed_board: Board<BOARD_WIDTH, BOARD_WIDTH> = Board::new();
+
+        for tick in 0..TICKS {
+            game_tick(&mut float_objects, launches.len(), &mut float_board, float_deceleration);
+            game_tick(&mut fixed_objects, launches.len(), &mut fixed_board, fixed_deceleration);
+
+            for (i, (float, fixed)) in float_objects.iter().zip(fixed_objects.iter()).take(launches.len()).enumerate() {
+                let (a, b) = match (float, fixed) {
+                    (Some(a), Some(b)) => (a.location(), b.location()),
+                    _ => continue,
+                };
+                let error = (a.x - b.x.to_f32()).abs().max((a.y - b.y.to_f32()).abs());
+                assert!(error <= TOLERANCE, "object {} tick {}: f32 ({:.3}, {:.3}) fixed ({:.3}, {:.3})",
+                    i, tick, a.x, a.y, b.x.to_f32(), b.y.to_f32());
+            }
+        }
+    }
+
+    #[test]
+    fn matches_the_f32_trajectories() {
+        for launches in SCENARIOS.iter() {
+            compare(launches);
+        }
+    }
+}
@@ -0,0 +1,466 @@
+/*
+ * 	projectile physics and link protocol of the wireless game
+ *
+ * 	shared between the firmware and the host side tools.
+ * 	the crate is no_std, the std feature is only needed
+ * 	by the host side binaries and the tests. the fixed-point feature runs
+ * 	the physics on Q16.16 instead of f32.
+ */
+
+#![cfg_attr(not(any(test, feature = "std")), no_std)]
+
+pub mod scalar;
+pub mod fixed;
+pub mod board;
+pub mod link;
+pub mod control;
+
+pub use scalar::Scalar;
+pub use fixed::Fixed;
+pub use board::{Board, Cell};
+
+// number type the physics runs on in the firmware
+#[cfg(feature = "fixed-point")]
+pub type Real = Fixed;
+#[cfg(not(feature = "fixed-point"))]
+pub type Real = f32;
+
+pub const BOARD_WIDTH: usize = 16;
+
+// default slowdown of -0.08 cells per tick, as a ratio so it
+// is exact in both number types
+pub const DECELERATION: (i32, i32) = (-8, 100);
+
+pub const MAXIMUM_OBJECTS: usize = 10;
+const MAX_COLLISIONS_PER_OBJECT: usize = 10;
+const MAXIMUM_COLLISIONS: usize = MAXIMUM_OBJECTS * MAX_COLLISIONS_PER_OBJECT;
+
+fn find_collision_times<S: Scalar>(start1: Vector<S>, v1: Vector<S>, start2: Vector<S>, v2: Vector<S>, radius: S) -> S {
+    let two = S::from_int(2);
+    let a: S = pow2(v1.x - v2.x) + pow2(v1.y - v2.y);
+
+    let b: S = -two * (v1.x - v2.x) * (start2.x - start1.x)
+        - two * (v1.y - v2.y) * (start2.y - start1.y);
+
+    let c: S = pow2(start1.x - start2.x) +
+        pow2(start1.y - start2.y) - pow2(radius);
+
+    if a.abs() < S::EPSILON {
+        return -c / b;
+    }
+    if a.abs() < S::EPSILON && b.abs() < S::EPSILON {
+        return -S::ONE;
+    }
+    S::solve_quadratic(a, b, c)
+}
+
+/// what a moving object ran into
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CollisionTarget {
+    LeftWall,
+    RightWall,
+    TopWall,
+    BottomWall,
+    /// index of the other object in the object array
+    Object(usize),
+}
+
+impl CollisionTarget {
+    pub fn is_wall(self) -> bool {
+        !matches!(self, CollisionTarget::Object(_))
+    }
+}
+
+/// a single collision, time is the fraction of the tick when it happened
+#[derive(Debug, Copy, Clone)]
+pub struct Collision<S = Real> {
+    pub object: usize,
+    pub target: CollisionTarget,
+    pub time: S,
+}
+
+/// the collisions that happened during one game_tick, in order
+#[derive(Copy, Clone)]
+pub struct Collisions<S = Real> {
+    events: [Option<Collision<S>>; MAXIMUM_COLLISIONS],
+    len: usize,
+}
+
+impl<S: Scalar> Collisions<S> {
+    fn new() -> Self {
+        Collisions { events: [None; MAXIMUM_COLLISIONS], len: 0 }
+    }
+
+    // collisions over the capacity are dropped, the physics still handles them
+    fn push(&mut self, collision: Collision<S>) {
+        if self.len < MAXIMUM_COLLISIONS {
+            self.events[self.len] = Some(collision);
+            self.len += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Collision<S>> + '_ {
+        self.events[..self.len].iter().filter_map(|c| *c)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Vector<S = Real> {
+    pub x: S,
+    pub y: S,
+}
+
+
+#[derive(Debug, Copy, Clone)]
+pub struct MovingObject<S = Real> {
+    velocity: Vector<S>,
+    location: Vector<S>,
+    ratios: Vector<S>,
+    /// index of the player that shot the object
+    pub owner: u8,
+    age: usize,
+}
+
+impl<S: Scalar> MovingObject<S> {
+    pub fn new(starting_location: Vector<S>, starting_velocity: Vector<S>, owner: u8) -> MovingObject<S> {
+        let location = starting_location;
+        let sum = starting_velocity.x.abs() + starting_velocity.y.abs();
+        if sum != S::ZERO {
+            let temp = Vector { x: starting_velocity.x.abs() / sum, y: starting_velocity.y.abs() / sum };
+            MovingObject {
+                velocity: starting_velocity,
+                location,
+                owner,
+                ratios: temp,
+                age: 0,
+            }
+        } else {
+            let half = S::from_ratio(1, 2);
+            MovingObject {
+                velocity: starting_velocity,
+                location,
+                owner,
+                ratios: Vector { x: half / sum, y: half },
+                age: 0,
+            }
+        }
+    }
+
+    fn tick(&mut self, time: S, final_: bool, collision_velocity: Option<Vector<S>>, deceleration: S) {
+        if !self.moving() {
+            return;
+        }
+        self.location = Vector {
+            x: self.location.x + self.velocity.x * time,
+            y: self.location.y + self.velocity.y * time,
+        };
+
+        if let Some(x) = collision_velocity {
+            self.velocity = x;
+        }
+
+        if final_ {
+            let x_vel = if self.velocity.x < S::ZERO {
+                min(S::ZERO, self.velocity.x - deceleration * self.ratios.x)
+            } else {
+                max(S::ZERO, self.velocity.x + deceleration * self.ratios.x)
+            };
+            let y_vel = if self.velocity.y < S::ZERO {
+                min(S::ZERO, self.velocity.y - deceleration * self.ratios.y)
+            } else {
+                max(S::ZERO, self.velocity.y + deceleration * self.ratios.y)
+            };
+            self.velocity = Vector { x: x_vel, y: y_vel };
+        }
+    }
+
+    fn get_collisions(self,
+                      others: [Option<MovingObject<S>>; MAXIMUM_OBJECTS],
+                      offset: usize,
+                      max_duration: S,
+                      inside: Vector<S>, ) -> [Option<Collision<S>>; MAX_COLLISIONS_PER_OBJECT] {
+        let new_loc = Vector {
+            x: self.location.x + self.velocity.x,
+            y: self.location.y + self.velocity.y,
+        };
+
+        let mut num_collisions: usize = 0;
+        let mut collisions: [Option<Collision<S>>; MAX_COLLISIONS_PER_OBJECT] = [None; MAX_COLLISIONS_PER_OBJECT];
+        let collision = |target: CollisionTarget, time: S| Some(Collision { object: offset, target, time });
+
+        if new_loc.x < S::ZERO && (self.location.x / self.velocity.x).abs() < max_duration {
+            collisions[num_collisions] = collision(CollisionTarget::LeftWall, (self.location.x / self.velocity.x).abs());
+            num_collisions += 1;
+        }
+        if new_loc.x > inside.x &&
+            ((inside.x - self.location.x) / self.velocity.x).abs() < max_duration {
+            collisions[num_collisions] = collision(CollisionTarget::RightWall, ((inside.x - self.location.x) / self.velocity.x).abs());
+            num_collisions += 1;
+        }
+        if new_loc.y < S::ZERO && (self.location.y / self.velocity.y).abs() < max_duration {
+            collisions[num_collisions] = collision(CollisionTarget::TopWall, (self.location.y / self.velocity.y).abs());
+            num_collisions += 1;
+        }
+        if new_loc.y > inside.y &&
+            ((inside.y - self.location.y) / self.velocity.y).abs() < max_duration {
+            collisions[num_collisions] = collision(CollisionTarget::BottomWall, ((inside.y - self.location.y) / self.velocity.y).abs());
+            num_collisions += 1;
+        }
+
+        for (i, other) in others.iter().enumerate().skip(offset + 1) {
+            let other = match *other {
+                Some(o) => o,
+                None => continue
+            };
+            if !self.moving() && !other.moving() {
+                continue;
+            }
+            let collision_time = find_collision_times(
+                self.location, self.velocity, other.location, other.velocity, S::ONE,
+            );
+            if S::ZERO < collision_time && collision_time < max_duration {
+                collisions[num_collisions] = collision(CollisionTarget::Object(i), collision_time);
+                num_collisions += 1;
+            }
+        }
+        collisions
+    }
+
+    pub fn position(self) -> (usize, usize) {
+        let offset = S::from_ratio(3, 2);
+        ((self.location.x + offset).to_usize(), (self.location.y + offset).to_usize())
+    }
+
+    pub fn location(self) -> Vector<S> {
+        self.location
+    }
+
+    pub fn velocity(self) -> Vector<S> {
+        self.velocity
+    }
+
+    pub fn moving(self) -> bool {
+        self.velocity.x != S::ZERO || self.velocity.y != S::ZERO
+    }
+    pub fn add_age(&mut self)  {
+        self.age += 1;
+    }
+    pub fn get_age(self) -> usize {
+        self.age
+    }
+}
+
+/// advances the objects by one tick, bouncing them off the walls of the board
+/// and each other, and slows them down by deceleration (negative). wall hits
+/// are marked on the board, the collisions that happened during the tick are
+/// returned
+pub fn game_tick<S: Scalar, const W: usize, const H: usize>(
+    objects: &mut [Option<MovingObject<S>>; MAXIMUM_OBJECTS],
+    number_of_objects: usize,
+    board: &mut Board<W, H>,
+    deceleration: S,
+) -> Collisions<S> {
+    let two = S::from_int(2);
+    // the objects move between the walls, offset by one cell
+    let inside = Vector { x: S::from_int(W as i32 - 3), y: S::from_int(H as i32 - 3) };
+    let offset = S::from_ratio(3, 2);
+    let mut events = Collisions::new();
+    let mut tick_so_far = S::ZERO;
+    while tick_so_far < S::ONE {
+        let mut collision_velocities: [Option<Vector<S>>; MAXIMUM_OBJECTS] = [None; MAXIMUM_OBJECTS];
+        let mut all_collisions: [Option<Collision<S>>; MAXIMUM_COLLISIONS] = [None; MAXIMUM_COLLISIONS];
+        let mut total_collisions: usize = 0;
+        for i in 0..number_of_objects {
+            let ob = match objects[i] {
+                Some(o) => o,
+                None => continue,
+            };
+            let temp = ob.get_collisions(*objects, i, S::ONE - tick_so_far, inside);
+            for collision in temp.iter() {
+                if collision.is_none() {
+                    break;
+                }
+                all_collisions[total_collisions] = *collision;
+                total_collisions += 1;
+            }
+        }
+        let mut duration = S::ONE - tick_so_far;
+
+        let mut num_used_collisions = 0;
+        let mut used_collisions: [Option<Collision<S>>; MAXIMUM_COLLISIONS] = [None; MAXIMUM_COLLISIONS];
+
+        if total_collisions != 0 {
+            let mut first_collision = S::ONE;
+
+            for collision in &all_collisions {
+                let time = match collision {
+                    Some(t) => t.time,
+                    None => break
+                };
+                first_collision = min(first_collision, time);
+            }
+
+            let hundred = S::from_int(100);
+            first_collision = (first_collision * hundred + S::ONE) / hundred;
+            duration = first_collision;
+
+            for collision in &all_collisions {
+                let time = match collision {
+                    Some(t) => t.time,
+                    None => break
+                };
+                if time <= first_collision {
+                    used_collisions[num_used_collisions] = *collision;
+                    num_used_collisions += 1;
+                }
+            }
+        }
+        for collision in &used_collisions {
+            let (target, first_idx) = match collision {
+                Some(c) => (c.target, c.object),
+                None => break,
+            };
+            let mut first_collider = match objects[first_idx] {
+                Some(o) => o,
+                None => panic!()
+            };
+
+            if let CollisionTarget::Object(other_idx) = target {
+                let mut other_collider = match objects[other_idx] {
+                    Some(o) => o,
+                    None => panic!()
+                };
+                if other_collider.moving() && first_collider.moving() {
+                    core::mem::swap(&mut first_collider.ratios, &mut other_collider.ratios);
+
+                    let total_velocity = (
+                        (pow2(first_collider.velocity.x) + pow2(first_collider.velocity.y)).sqrt() +
+                            (pow2(other_collider.velocity.x) + pow2(other_collider.velocity.y)).sqrt()
+                    ) / two;
+
+                    collision_velocities[other_idx] = Some(Vector {
+                        x: total_velocity * other_collider.ratios.x * sign(first_collider.velocity.x),
+                        y: total_velocity * other_collider.ratios.y * sign(first_collider.velocity.y),
+                    });
+
+                    collision_velocities[first_idx] = Some(Vector {
+                        x: total_velocity * first_collider.ratios.x * sign(other_collider.velocity.x),
+                        y: total_velocity * first_collider.ratios.y * sign(other_collider.velocity.y),
+                    });
+                } else if first_collider.moving() {
+                    collision_velocities[first_idx] = Some(Vector {
+                        x: -first_collider.velocity.x,
+                        y: -first_collider.velocity.y,
+                    });
+                } else {
+                    collision_velocities[other_idx] = Some(Vector {
+                        x: -other_collider.velocity.x,
+                        y: -other_collider.velocity.y,
+                    });
+                }
+            } else if target == CollisionTarget::LeftWall || target == CollisionTarget::RightWall {
+                collision_velocities[first_idx] = Some(Vector {
+                    x: -first_collider.velocity.x,
+                    y: first_collider.velocity.y,
+                });
+            } else {
+                collision_velocities[first_idx] = Some(Vector {
+                    x: first_collider.velocity.x,
+                    y: -first_collider.velocity.y,
+                });
+            }
+        }
+        for i in 0..number_of_objects {
+            let mut temp = match objects[i] {
+                Some(o) => o,
+                None => continue
+            };
+            temp.tick(duration,
+                      tick_so_far + duration >= S::ONE,
+                      collision_velocities[i],
+                      deceleration);
+            objects[i] = Some(
+                temp
+            );
+        }
+
+        if num_used_collisions != 0 {
+            for collision in &used_collisions {
+                let collision = match collision {
+                    Some(c) => *c,
+                    None => break,
+                };
+                let object = match objects[collision.object] {
+                    Some(o) => o,
+                    None => panic!()
+                };
+                let x = (object.location.x + offset).to_usize();
+                let y = (object.location.y + offset).to_usize();
+                let hit = Cell::WallHit(object.owner);
+                // a hit outside of the board is not marked
+                let _ = match collision.target {
+                    CollisionTarget::LeftWall => board.set(0, y, hit),
+                    CollisionTarget::RightWall => board.set(W - 1, y, hit),
+                    CollisionTarget::TopWall => board.set(x, 0, hit),
+                    CollisionTarget::BottomWall => board.set(x, H - 1, hit),
+                    CollisionTarget::Object(_) => Ok(()),
+                };
+                events.push(Collision { time: tick_so_far + collision.time, ..collision });
+            }
+        }
+        tick_so_far = tick_so_far + duration;
+    }
+    events
+}
+
+
+#[repr(C)]
+union MyUnion {
+    f1: u32,
+    f2: f32,
+}
+
+pub fn fast_sqrt(num: f32) -> f32 {
+    let x2 = num * 0.5f32;
+    let threehalfs = 1.5f32;
+
+    let mut conv = MyUnion { f2: num };
+    unsafe {
+        conv.f1 = 0x5f3759df - (conv.f1 >> 1);
+
+        conv.f2 *= threehalfs - (x2 * conv.f2 * conv.f2);
+        1f32 / conv.f2
+    }
+}
+
+pub fn abs(num: f32) -> f32 {
+    let mut conv = MyUnion { f2: num };
+    unsafe {
+        conv.f1 &= 0x7fffffff;
+        conv.f2
+    }
+}
+
+pub fn pow2<S: Scalar>(num: S) -> S {
+    num * num
+}
+
+fn min<S: Scalar>(first: S, second: S) -> S {
+    if first < second { first } else { second }
+}
+
+fn max<S: Scalar>(first: S, second: S) -> S {
+    if first > second { first } else { second }
+}
+
+fn sign<S: Scalar>(num: S) -> S {
+    if num < S::ZERO { -S::ONE } else { S::ONE }
+}
\ No newline at end of file
@@ -0,0 +1,552 @@
+/*
+ * 	framed binary protocol of the uart link
+ *
+ * 	every message is sent as one frame:
+ *
+ * 	  SYNC | kind | source | length | seq | payload | crc16
+ *
+ * 	source is the player id of the sender and seq counts the
+ * 	frames of each sender. the crc (CCITT, little endian)
+ * 	covers everything after the sync byte. the decoder finds
+ * 	the frames from a stream that can also contain stray
+ * 	text, dropped and corrupted bytes.
+ *
+ * 	the wifi adapter sends its own frames to the board it is
+ * 	wired to with the last source id, see control.rs.
+ */
+
+pub const SYNC: u8 = 0xAA;
+pub const MAX_PAYLOAD: usize = 128;
+// kind, source, length and seq
+const HEADER_LEN: usize = 4;
+pub const MAX_FRAME: usize = 1 + HEADER_LEN + MAX_PAYLOAD + 2;
+// senders the decoder keeps a sequence number for
+pub const MAX_SOURCES: usize = 8;
+// the wifi adapter, a board never sends with it
+pub const ADAPTER_SOURCE: u8 = (MAX_SOURCES - 1) as u8;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FrameKind {
+    // nunchuk data of a client: joy_x, joy_y, btn_z, btn_c
+    Input = 0x01,
+    // cells of the master screen that changed, see stream.rs of the firmware
+    ScreenDelta = 0x02,
+    // the whole master screen
+    ScreenResync = 0x03,
+    // a client asks the master for a ScreenResync, no payload
+    ResyncRequest = 0x04,
+    // the master measures the round trip time: id, time
+    Ping = 0x05,
+    // a client sends the payload of a ping back as it is
+    Pong = 0x06,
+    // the master starts a lockstep match, see lockstep.rs of the firmware
+    LockstepStart = 0x07,
+    // inputs of a device tagged with the ticks they are played at
+    LockstepInput = 0x08,
+    // checksum of the match after a tick
+    LockstepChecksum = 0x09,
+    // a spectator watches the match, no payload. sent once a second while it watches
+    Spectate = 0x0A,
+    // a board tells the master its protocol version and what it can do, see handshake.rs of the firmware
+    Hello = 0x0B,
+    // the master accepts or refuses the board of a hello
+    HelloAck = 0x0C,
+    // an event of the wifi adapter to its own board, see control.rs
+    Adapter = 0x0D,
+}
+
+impl FrameKind {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(FrameKind::Input),
+            0x02 => Some(FrameKind::ScreenDelta),
+            0x03 => Some(FrameKind::ScreenResync),
+            0x04 => Some(FrameKind::ResyncRequest),
+            0x05 => Some(FrameKind::Ping),
+            0x06 => Some(FrameKind::Pong),
+            0x07 => Some(FrameKind::LockstepStart),
+            0x08 => Some(FrameKind::LockstepInput),
+            0x09 => Some(FrameKind::LockstepChecksum),
+            0x0A => Some(FrameKind::Spectate),
+            0x0B => Some(FrameKind::Hello),
+            0x0C => Some(FrameKind::HelloAck),
+            0x0D => Some(FrameKind::Adapter),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkError {
+    // the checksum did not match, the frame was dropped
+    BadCrc,
+    // length over MAX_PAYLOAD
+    TooLong(u8),
+    UnknownKind(u8),
+    // source over MAX_SOURCES
+    UnknownSource(u8),
+    // an older or repeated frame of the sender, it was dropped
+    OutOfOrder { source: u8, expected: u8, received: u8 },
+    // the output buffer is too small for the frame
+    BufferTooSmall,
+}
+
+#[derive(Copy, Clone)]
+pub struct Frame {
+    pub kind: FrameKind,
+    pub source: u8,
+    pub seq: u8,
+    len: u8,
+    data: [u8; MAX_PAYLOAD],
+}
+
+impl Frame {
+    pub fn new(kind: FrameKind, source: u8, seq: u8, payload: &[u8]) -> Result<Self, LinkError> {
+        if payload.len() > MAX_PAYLOAD {
+            return Err(LinkError::TooLong(payload.len() as u8));
+        }
+        let mut data = [0u8; MAX_PAYLOAD];
+        data[..payload.len()].copy_from_slice(payload);
+        Ok(Frame { kind, source, seq, len: payload.len() as u8, data })
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.data[..self.len as usize]
+    }
+
+    /// writes the frame to out, returns the number of bytes written
+    pub fn encode(&self, out: &mut [u8]) -> Result<usize, LinkError> {
+        let len = 1 + HEADER_LEN + self.len as usize + 2;
+        if out.len() < len {
+            return Err(LinkError::BufferTooSmall);
+        }
+        out[0] = SYNC;
+        out[1] = self.kind as u8;
+        out[2] = self.source;
+        out[3] = self.len;
+        out[4] = self.seq;
+        out[5..5 + self.len as usize].copy_from_slice(self.payload());
+        let crc = crc16(&out[1..len - 2]);
+        out[len - 2] = crc as u8;
+        out[len - 1] = (crc >> 8) as u8;
+        Ok(len)
+    }
+}
+
+/// numbers the frames of one sender
+pub struct Encoder {
+    source: u8,
+    seq: u8,
+}
+
+impl Encoder {
+    pub fn new(source: u8) -> Self {
+        Encoder { source, seq: 0 }
+    }
+
+    /// encodes the next frame to out, returns the number of bytes written
+    pub fn encode(&mut self, kind: FrameKind, payload: &[u8], out: &mut [u8]) -> Result<usize, LinkError> {
+        let len = Frame::new(kind, self.source, self.seq, payload)?.encode(out)?;
+        self.seq = self.seq.wrapping_add(1);
+        Ok(len)
+    }
+}
+
+/// counters of the decoded stream
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct LinkStats {
+    pub frames: u32,
+    // frames dropped for a bad crc, length, kind or source
+    pub corrupted: u32,
+    // late and repeated frames that were dropped
+    pub out_of_order: u32,
+    // frames missing from the sequence numbers
+    pub lost: u32,
+}
+
+enum Step {
+    Pending,
+    Frame(Frame),
+    // the bytes after the sync have to be searched for a new frame
+    Rescan(LinkError),
+    Drop(LinkError),
+}
+
+pub struct Decoder {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+    // next sequence number expected from each source
+    expected: [Option<u8>; MAX_SOURCES],
+    pub stats: LinkStats,
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Decoder {
+            buf: [0; MAX_FRAME],
+            len: 0,
+            expected: [None; MAX_SOURCES],
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// forgets the sequence numbers, e.g. when the link was reconnected
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.expected = [None; MAX_SOURCES];
+    }
+
+    /// decodes the bytes, calls on_frame for every frame and every error found.
+    /// a frame can be split over any number of calls
+    pub fn feed<F: FnMut(Result<Frame, LinkError>)>(&mut self, bytes: &[u8], mut on_frame: F) {
+        for byte in bytes {
+            self.feed_byte(*byte, &mut on_frame);
+        }
+    }
+}
+
+// private methods
+impl Decoder {
+    fn feed_byte<F: FnMut(Result<Frame, LinkError>)>(&mut self, byte: u8, on_frame: &mut F) {
+        // bytes waiting to be decoded, the ones of a broken frame are decoded
+        // again after its sync byte. they never add up to more than a frame
+        let mut work = [0u8; MAX_FRAME + 1];
+        work[0] = byte;
+        let mut count = 1;
+        let mut i = 0;
+
+        while i < count {
+            let step = self.step(work[i]);
+            i += 1;
+            match step {
+                Step::Pending => {}
+                Step::Frame(frame) => on_frame(Ok(frame)),
+                Step::Drop(error) => on_frame(Err(error)),
+                Step::Rescan(error) => {
+                    on_frame(Err(error));
+                    let rest = count - i;
+                    let again = self.len - 1;
+                    let mut next = [0u8; MAX_FRAME + 1];
+                    next[..again].copy_from_slice(&self.buf[1..self.len]);
+                    next[again..again + rest].copy_from_slice(&work[i..count]);
+                    work = next;
+                    count = again + rest;
+                    i = 0;
+                    self.len = 0;
+                }
+            }
+        }
+    }
+
+    fn step(&mut self, byte: u8) -> Step {
+        // Skip everything between the frames
+        if self.len == 0 && byte != SYNC {
+            return Step::Pending;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        if self.len == 1 + HEADER_LEN {
+            if FrameKind::from_u8(self.buf[1]).is_none() {
+                self.stats.corrupted += 1;
+                return Step::Rescan(LinkError::UnknownKind(self.buf[1]));
+            }
+            if self.buf[2] as usize >= MAX_SOURCES {
+                self.stats.corrupted += 1;
+                return Step::Rescan(LinkError::UnknownSource(self.buf[2]));
+            }
+            if self.buf[3] as usize > MAX_PAYLOAD {
+                self.stats.corrupted += 1;
+                return Step::Rescan(LinkError::TooLong(self.buf[3]));
+            }
+        }
+        if self.len < 1 + HEADER_LEN || self.len < 1 + HEADER_LEN + self.buf[3] as usize + 2 {
+            return Step::Pending;
+        }
+
+        let len = self.len;
+        let received = self.buf[len - 2] as u16 | (self.buf[len - 1] as u16) << 8;
+        if crc16(&self.buf[1..len - 2]) != received {
+            self.stats.corrupted += 1;
+            return Step::Rescan(LinkError::BadCrc);
+        }
+        self.len = 0;
+
+        let kind = match FrameKind::from_u8(self.buf[1]) {
+            Some(kind) => kind,
+            None => return Step::Drop(LinkError::UnknownKind(self.buf[1])),
+        };
+        let source = self.buf[2];
+        let seq = self.buf[4];
+
+        // Older frames than the newest one are dropped, missing ones are only counted.
+        // The adapter starts counting again when it restarts, its frames are all kept
+        if let Some(expected) = self.expected[source as usize].filter(|_| source != ADAPTER_SOURCE) {
+            let ahead = seq.wrapping_sub(expected) as i8;
+            if ahead < 0 {
+                self.stats.out_of_order += 1;
+                return Step::Drop(LinkError::OutOfOrder { source, expected, received: seq });
+            }
+            self.stats.lost += ahead as u32;
+        }
+        self.expected[source as usize] = Some(seq.wrapping_add(1));
+        self.stats.frames += 1;
+
+        match Frame::new(kind, source, seq, &self.buf[5..len - 2]) {
+            Ok(frame) => Step::Frame(frame),
+            Err(error) => Step::Drop(error),
+        }
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+/// CRC-16/CCITT-FALSE
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0xFFFF;
+    for byte in bytes {
+        crc ^= (*byte as u16) << 8;
+        for _ in 0..8 {
+            if crc & 0x8000 != 0 {
+                crc = (crc << 1) ^ 0x1021;
+            } else {
+                crc <<= 1;
+            }
+        }
+    }
+    crc
+}
+
+// Byte streams with the kinds of damage the uart link sees: text from the wifi adapter,
+// garbage, flipped and dropped bytes, repeated and reordered frames. no frame may be lost,
+// made up or go unreported
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Encoder, Frame, FrameKind, LinkError, LinkStats, MAX_FRAME};
+
+    const FRAMES: usize = 2000;
+    const SOURCES: u8 = 4;
+
+    // xorshift, so every run checks the same streams
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            self.next() as usize % n
+        }
+    }
+
+    struct Sent {
+        source: u8,
+        seq: u8,
+        payload: Vec<u8>,
+        bytes: Vec<u8>,
+    }
+
+    // frames from all sources interleaved, like the master receives them
+    fn frames(random: &mut Random) -> Vec<Sent> {
+        let mut encoders: Vec<Encoder> = (0..SOURCES).map(Encoder::new).collect();
+        let mut seqs = vec![0u8; SOURCES as usize];
+        let mut sent = Vec::new();
+
+        for _ in 0..FRAMES {
+            let source = random.below(SOURCES as usize);
+            let payload: Vec<u8> = (0..random.below(12)).map(|_| random.next() as u8).collect();
+            let mut bytes = [0u8; MAX_FRAME];
+            let len = encoders[source].encode(FrameKind::Input, &payload, &mut bytes).unwrap();
+            sent.push(Sent {
+                source: source as u8,
+                seq: seqs[source],
+                payload,
+                bytes: bytes[..len].to_vec(),
+            });
+            seqs[source] = seqs[source].wrapping_add(1);
+        }
+        sent
+    }
+
+    // decodes the stream in random sized pieces
+    fn decode(stream: &[u8], random: &mut Random) -> (Vec<Frame>, Vec<LinkError>, LinkStats) {
+        let mut decoder = Decoder::new();
+        let mut decoded = Vec::new();
+        let mut errors = Vec::new();
+
+        // The link never goes quiet, the adapter keeps sending NOP lines. a frame
+        // with a damaged length is only noticed when enough bytes have followed it
+        let mut stream = stream.to_vec();
+        for _ in 0..(MAX_FRAME / 4 + 1) {
+            stream.extend_from_slice(b"NOP\n");
+        }
+
+        let mut at = 0;
+        while at < stream.len() {
+            let end = (at + 1 + random.below(40)).min(stream.len());
+            decoder.feed(&stream[at..end], |result| match result {
+                Ok(frame) => decoded.push(frame),
+                Err(error) => errors.push(error),
+            });
+            at = end;
+        }
+        (decoded, errors, decoder.stats)
+    }
+
+    fn same(frame: &Frame, sent: &Sent) -> bool {
+        sent.source == frame.source && sent.seq == frame.seq && sent.payload == frame.payload()
+    }
+
+    // every decoded frame has to be one of the sent ones, unchanged
+    fn made_up(decoded: &[Frame], sent: &[Sent]) -> usize {
+        decoded.iter().filter(|frame| !sent.iter().any(|s| same(frame, s))).count()
+    }
+
+    // the decoded frames are the sent ones in the same order, with some left out.
+    // the sequence numbers wrap, so the order is what tells the frames apart
+    fn in_order(decoded: &[Frame], sent: &[Sent]) -> bool {
+        let mut remaining = sent.iter();
+        decoded.iter().all(|frame| remaining.any(|s| same(frame, s)))
+    }
+
+    // the frames of the checks and the random numbers that damage them
+    fn setup() -> (Random, Vec<Sent>) {
+        let mut random = Random(0x1234_5678);
+        let sent = frames(&mut random);
+        (random, sent)
+    }
+
+
+    #[test]
+    fn clean() {
+        let (mut random, sent) = setup();
+        let stream: Vec<u8> = sent.iter().flat_map(|s| s.bytes.clone()).collect();
+        let (decoded, errors, stats) = decode(&stream, &mut random);
+        assert_eq!(decoded.len(), FRAMES);
+        assert!(errors.is_empty());
+        assert!(in_order(&decoded, &sent));
+        assert!(stats == LinkStats { frames: FRAMES as u32, ..LinkStats::default() });
+    }
+
+    // Text the wifi adapter writes to the same uart
+    #[test]
+    fn text() {
+        let (mut random, sent) = setup();
+        let texts = ["NOP\n", "TCP conn lost..\n", "Connected\r\n", "DATA"];
+        let mut stream = Vec::new();
+        for s in sent.iter() {
+            if random.below(3) == 0 {
+                stream.extend_from_slice(texts[random.below(texts.len())].as_bytes());
+            }
+            stream.extend_from_slice(&s.bytes);
+        }
+        let (decoded, errors, _) = decode(&stream, &mut random);
+        assert_eq!(decoded.len(), FRAMES);
+        assert!(errors.is_empty());
+        assert!(in_order(&decoded, &sent));
+    }
+
+    // Random bytes between the frames, including sync bytes
+    #[test]
+    fn garbage() {
+        let (mut random, sent) = setup();
+        let mut stream = Vec::new();
+        for s in sent.iter() {
+            for _ in 0..random.below(6) {
+                stream.push(if random.below(4) == 0 { 0xAA } else { random.next() as u8 });
+            }
+            stream.extend_from_slice(&s.bytes);
+        }
+        let (decoded, _, _) = decode(&stream, &mut random);
+        assert_eq!(decoded.len(), FRAMES);
+        assert!(in_order(&decoded, &sent));
+    }
+
+    // Flipped bits, every damaged frame has to be reported and dropped
+    #[test]
+    fn flipped() {
+        let (mut random, sent) = setup();
+        let mut stream = Vec::new();
+        let mut flipped = 0;
+        for s in sent.iter() {
+            let mut bytes = s.bytes.clone();
+            if random.below(10) == 0 {
+                let at = 1 + random.below(bytes.len() - 1);
+                bytes[at] ^= 1 << random.below(8);
+                flipped += 1;
+            }
+            stream.extend_from_slice(&bytes);
+        }
+        let (decoded, errors, stats) = decode(&stream, &mut random);
+        assert_eq!(decoded.len(), FRAMES - flipped);
+        assert!(in_order(&decoded, &sent));
+        assert!(errors.len() >= flipped);
+        assert!(stats.corrupted >= flipped as u32);
+    }
+
+    // Dropped bytes, the missing frames show up in the sequence numbers
+    #[test]
+    fn dropped() {
+        let (mut random, sent) = setup();
+        let mut stream = Vec::new();
+        let mut damaged = vec![false; FRAMES];
+        for (n, s) in sent.iter().enumerate() {
+            let mut bytes = s.bytes.clone();
+            if random.below(10) == 0 {
+                bytes.remove(random.below(bytes.len()));
+                damaged[n] = true;
+            }
+            stream.extend_from_slice(&bytes);
+        }
+        let dropped = damaged.iter().filter(|d| **d).count();
+        // A missing frame is noticed when a later frame of the same source arrives
+        let noticed = (0..FRAMES)
+            .filter(|n| damaged[*n] && (n + 1..FRAMES).any(|m| !damaged[m] && sent[m].source == sent[*n].source))
+            .count();
+        let (decoded, _, stats) = decode(&stream, &mut random);
+        assert_eq!(decoded.len(), FRAMES - dropped);
+        assert!(in_order(&decoded, &sent));
+        assert_eq!(stats.lost, noticed as u32);
+    }
+
+    // Repeated and swapped frames, the older ones are reported and dropped
+    #[test]
+    fn reordered() {
+        let (mut random, sent) = setup();
+        let mut order: Vec<usize> = (0..FRAMES).collect();
+        let mut swapped = 0;
+        let mut i = 0;
+        while i + 1 < FRAMES {
+            if sent[order[i]].source == sent[order[i + 1]].source && random.below(5) == 0 {
+                order.swap(i, i + 1);
+                swapped += 1;
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+        let mut stream = Vec::new();
+        let mut repeated = 0;
+        for n in order.iter() {
+            stream.extend_from_slice(&sent[*n].bytes);
+            if random.below(20) == 0 {
+                stream.extend_from_slice(&sent[*n].bytes);
+                repeated += 1;
+            }
+        }
+        let (decoded, errors, stats) = decode(&stream, &mut random);
+        let late = errors.iter().filter(|e| matches!(e, LinkError::OutOfOrder { .. })).count();
+        assert_eq!(made_up(&decoded, &sent), 0);
+        assert_eq!(late, swapped + repeated);
+        assert_eq!(stats.out_of_order, (swapped + repeated) as u32);
+        assert_eq!(decoded.len(), FRAMES - swapped);
+    }
+}
@@ -0,0 +1,90 @@
+/*
+ * 	number type used by the projectile physics
+ *
+ * 	the physics is written against the Scalar trait so the
+ * 	same code runs with f32 and with the Q16.16 fixed point
+ * 	type. the gd32vf103 has no fpu, so every f32 operation
+ * 	is a soft-float library call on the device.
+ */
+
+use core::fmt::Debug;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+
+pub trait Scalar:
+    Copy
+    + Debug
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    // smallest value considered different from zero
+    const EPSILON: Self;
+
+    fn from_int(num: i32) -> Self;
+
+    /// num / den, exact for the constants used by the physics
+    fn from_ratio(num: i32, den: i32) -> Self;
+
+    fn from_f32(num: f32) -> Self;
+
+    fn to_f32(self) -> f32;
+
+    /// truncates towards zero like `as usize`, negative values become 0
+    fn to_usize(self) -> usize;
+
+    fn abs(self) -> Self;
+
+    fn sqrt(self) -> Self;
+
+    /// smaller root of a*t^2 + b*t + c = 0, or -1 when there is none
+    fn solve_quadratic(a: Self, b: Self, c: Self) -> Self {
+        let two = Self::from_int(2);
+        let determinant = b * b - Self::from_int(4) * a * c;
+        // Since we only care about collisions that happen between t=[0, 1)
+        // it is ok to return -1 here
+        if determinant < Self::ZERO {
+            -Self::ONE
+        } else {
+            (-b - determinant.sqrt()) / (two * a)
+        }
+    }
+}
+
+impl Scalar for f32 {
+    const ZERO: Self = 0f32;
+    const ONE: Self = 1f32;
+    const EPSILON: Self = f32::EPSILON;
+
+    fn from_int(num: i32) -> Self {
+        num as f32
+    }
+
+    fn from_ratio(num: i32, den: i32) -> Self {
+        num as f32 / den as f32
+    }
+
+    fn from_f32(num: f32) -> Self {
+        num
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn to_usize(self) -> usize {
+        self as usize
+    }
+
+    fn abs(self) -> Self {
+        crate::abs(self)
+    }
+
+    fn sqrt(self) -> Self {
+        crate::fast_sqrt(self)
+    }
+}
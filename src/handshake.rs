@@ -0,0 +1,344 @@
+/*
+ * 	hello and ack between the clients and the master
+ *
+ * 	every client and spectator sends a hello once a second
+ * 	and right after its adapter connected. the master
+ * 	answers each one with an ack telling whether it takes
+ * 	part: the protocol versions have to be the same, the
+ * 	player id has to fit in the match, the client has to
+ * 	handle as many players as the match has and its
+ * 	controller has to be one the master knows. the master
+ * 	only plays the inputs of accepted players, a refused
+ * 	board shows a cross on its matrix.
+ *
+ * 	every board sends an id of its own with the hello. the
+ * 	first board accepted with a player id keeps it until
+ * 	its link goes down, another board sending the same id
+ * 	is refused. the ack repeats the board id, so only the
+ * 	board it answers takes it.
+ *
+ * 	hello: version, build (u32), board (u32), max players, controller
+ * 	ack:   version, player, board (u32), build (u32), player count, result
+ *
+ * 	the version is always the first byte, so any other
+ * 	version is refused even if the rest has changed.
+ */
+
+use crate::engine::MAX_PLAYERS;
+use crate::game::link::{ ADAPTER_SOURCE, MAX_SOURCES };
+
+/// changes whenever the frames of the link change
+pub const PROTOCOL_VERSION: u8 = 4;
+/// tells the builds of the firmware apart on the debug uart, the revision
+/// is set by build.rs at the root of the repository
+pub const BUILD_ID: u32 = build_id(env!("BUILD_REVISION"));
+pub const HELLO_LEN: usize = 11;
+pub const ACK_LEN: usize = 12;
+// loop frames between the hellos, a second
+pub const HELLO_INTERVAL: u32 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Controller {
+    Nunchuk = 1,
+    // the keyboard of the simulator
+    Keyboard = 2,
+    Computer = 3,
+    // a spectator does not play
+    Nothing = 4,
+}
+
+impl Controller {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Controller::Nunchuk),
+            2 => Some(Controller::Keyboard),
+            3 => Some(Controller::Computer),
+            4 => Some(Controller::Nothing),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Refusal {
+    Version = 1,
+    // the player id is not in the match
+    PlayerId = 2,
+    // the client handles fewer players than the match has
+    PlayerCount = 3,
+    Controller = 4,
+    // another board has been accepted with the player id
+    Taken = 5,
+}
+
+impl Refusal {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(Refusal::Version),
+            2 => Some(Refusal::PlayerId),
+            3 => Some(Refusal::PlayerCount),
+            4 => Some(Refusal::Controller),
+            5 => Some(Refusal::Taken),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Hello {
+    pub version: u8,
+    pub build: u32,
+    // tells the boards sending the same player id apart
+    pub board: u32,
+    pub max_players: u8,
+    // a Controller, kept as sent so an unknown one can be refused
+    pub controller: u8,
+}
+
+impl Hello {
+    /// the hello of this build sent by board
+    pub fn new(controller: Controller, board: u32) -> Self {
+        Hello {
+            version: PROTOCOL_VERSION,
+            build: BUILD_ID,
+            board,
+            max_players: MAX_PLAYERS as u8,
+            controller: controller as u8,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HELLO_LEN] {
+        let b = self.build.to_le_bytes();
+        let d = self.board.to_le_bytes();
+        [self.version, b[0], b[1], b[2], b[3], d[0], d[1], d[2], d[3], self.max_players, self.controller]
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        // Only the version of another protocol can be read
+        match bytes.first() {
+            Some(version) if *version != PROTOCOL_VERSION => {
+                return Some(Hello { version: *version, build: 0, board: 0, max_players: 0, controller: 0 });
+            }
+            _ => {}
+        }
+        if bytes.len() != HELLO_LEN {
+            return None;
+        }
+        Some(Hello {
+            version: bytes[0],
+            build: u32::from_le_bytes([bytes[1], bytes[2], bytes[3], bytes[4]]),
+            board: u32::from_le_bytes([bytes[5], bytes[6], bytes[7], bytes[8]]),
+            max_players: bytes[9],
+            controller: bytes[10],
+        })
+    }
+
+    /// whether the sender can take part in a match of player_count players
+    pub fn check(&self, source: usize, player_count: usize) -> Result<(), Refusal> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(Refusal::Version);
+        }
+        // Spectators use the ids after the players, the last one is the adapter's
+        if source >= ADAPTER_SOURCE as usize || (source >= player_count && source < MAX_PLAYERS) || source == 0 {
+            return Err(Refusal::PlayerId);
+        }
+        if (self.max_players as usize) < player_count {
+            return Err(Refusal::PlayerCount);
+        }
+        match Controller::from_u8(self.controller) {
+            Some(Controller::Nothing) if source < MAX_PLAYERS => Err(Refusal::Controller),
+            Some(_) => Ok(()),
+            None => Err(Refusal::Controller),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ack {
+    pub version: u8,
+    // the ids of the board the ack answers, the master sends it to everyone
+    pub player: u8,
+    pub board: u32,
+    pub build: u32,
+    pub player_count: u8,
+    pub result: Result<(), Refusal>,
+}
+
+impl Ack {
+    pub fn encode(&self) -> [u8; ACK_LEN] {
+        let d = self.board.to_le_bytes();
+        let b = self.build.to_le_bytes();
+        let result = match self.result {
+            Ok(()) => 0,
+            Err(refusal) => refusal as u8,
+        };
+        [self.version, self.player, d[0], d[1], d[2], d[3], b[0], b[1], b[2], b[3], self.player_count, result]
+    }
+
+    /// an ack of another protocol version is a refusal, whoever it was sent to
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        match bytes.first() {
+            Some(version) if *version != PROTOCOL_VERSION => {
+                return Some(Ack { version: *version, player: 0, board: 0, build: 0, player_count: 0, result: Err(Refusal::Version) });
+            }
+            _ => {}
+        }
+        if bytes.len() != ACK_LEN {
+            return None;
+        }
+        let result = match bytes[11] {
+            0 => Ok(()),
+            code => Err(Refusal::from_u8(code)?),
+        };
+        Some(Ack {
+            version: bytes[0],
+            player: bytes[1],
+            board: u32::from_le_bytes([bytes[2], bytes[3], bytes[4], bytes[5]]),
+            build: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], bytes[9]]),
+            player_count: bytes[10],
+            result,
+        })
+    }
+
+    /// whether the ack answers the hello of board sent with the player id
+    pub fn answers(&self, player: u8, board: u32) -> bool {
+        self.version != PROTOCOL_VERSION || (self.player == player && self.board == board)
+    }
+}
+
+/// the hellos the master got, by the id of the sender
+pub struct Peers {
+    player_count: usize,
+    results: [Option<Result<(), Refusal>>; MAX_SOURCES],
+    // the board accepted with each id
+    boards: [Option<u32>; MAX_SOURCES],
+}
+
+impl Peers {
+    pub fn new(player_count: usize) -> Self {
+        Peers { player_count, results: [None; MAX_SOURCES], boards: [None; MAX_SOURCES] }
+    }
+
+    /// checks a hello, returns the ack to send
+    pub fn hello(&mut self, source: usize, hello: &Hello) -> Ack {
+        let taken = matches!(self.boards.get(source), Some(Some(board)) if *board != hello.board);
+        let result = if taken {
+            Err(Refusal::Taken)
+        } else {
+            hello.check(source, self.player_count)
+        };
+        // The board holding the id stays accepted
+        if !taken {
+            if let Some(slot) = self.results.get_mut(source) {
+                *slot = Some(result);
+            }
+            if let Some(slot) = self.boards.get_mut(source) {
+                *slot = result.ok().map(|_| hello.board);
+            }
+        }
+        Ack {
+            version: PROTOCOL_VERSION,
+            player: source as u8,
+            board: hello.board,
+            build: BUILD_ID,
+            player_count: self.player_count as u8,
+            result,
+        }
+    }
+
+    /// the link to the board with the id went down, any board can take it again
+    pub fn forget(&mut self, source: usize) {
+        if let Some(slot) = self.results.get_mut(source) {
+            *slot = None;
+        }
+        if let Some(slot) = self.boards.get_mut(source) {
+            *slot = None;
+        }
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.player_count
+    }
+
+    pub fn accepted(&self, source: usize) -> bool {
+        matches!(self.results.get(source), Some(Some(Ok(()))))
+    }
+
+    pub fn refusal(&self, source: usize) -> Option<Refusal> {
+        match self.results.get(source) {
+            Some(Some(Err(refusal))) => Some(*refusal),
+            _ => None,
+        }
+    }
+
+    /// the first of the players that has not been accepted
+    pub fn waiting_for(&self, players: core::ops::Range<usize>) -> Option<usize> {
+        players.into_iter().find(|player| !self.accepted(*player))
+    }
+}
+
+// FNV-1a of the text, a const fn so the id is known at compile time
+const fn build_id(text: &str) -> u32 {
+    let bytes = text.as_bytes();
+    let mut hash: u32 = 0x811C_9DC5;
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u32).wrapping_mul(0x0100_0193);
+        i += 1;
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: u32 = 0x1234_5678;
+    const OTHER_BOARD: u32 = 0x8765_4321;
+
+    fn hello(board: u32) -> Hello {
+        Hello::new(Controller::Keyboard, board)
+    }
+
+    #[test]
+    fn hello_and_ack_survive_encoding() {
+        let sent = hello(BOARD);
+        assert_eq!(Hello::decode(&sent.encode()), Some(sent));
+        let ack = Peers::new(2).hello(1, &sent);
+        assert_eq!(Ack::decode(&ack.encode()), Some(ack));
+        assert!(ack.answers(1, BOARD));
+        assert!(!ack.answers(1, OTHER_BOARD));
+    }
+
+    #[test]
+    fn another_version_is_refused_from_the_first_byte() {
+        let mut bytes = hello(BOARD).encode();
+        bytes[0] = PROTOCOL_VERSION + 1;
+        assert_eq!(Hello::decode(&bytes[..2]).map(|h| h.check(1, 2)), Some(Err(Refusal::Version)));
+
+        let mut bytes = Peers::new(2).hello(1, &hello(BOARD)).encode();
+        bytes[0] = PROTOCOL_VERSION + 1;
+        let ack = Ack::decode(&bytes[..1]).unwrap();
+        assert_eq!(ack.result, Err(Refusal::Version));
+        assert!(ack.answers(1, OTHER_BOARD));
+    }
+
+    #[test]
+    fn a_second_board_with_the_same_id_is_refused() {
+        let mut peers = Peers::new(2);
+        assert_eq!(peers.hello(1, &hello(BOARD)).result, Ok(()));
+        assert_eq!(peers.hello(1, &hello(OTHER_BOARD)).result, Err(Refusal::Taken));
+        // The first board keeps playing
+        assert!(peers.accepted(1));
+        assert_eq!(peers.hello(1, &hello(BOARD)).result, Ok(()));
+    }
+
+    #[test]
+    fn a_forgotten_id_can_be_taken() {
+        let mut peers = Peers::new(2);
+        peers.hello(1, &hello(BOARD));
+        peers.forget(1);
+        assert!(!peers.accepted(1));
+        assert_eq!(peers.hello(1, &hello(OTHER_BOARD)).result, Ok(()));
+    }
+}
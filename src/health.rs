@@ -0,0 +1,53 @@
+/*
+ * 	health of the link to the other devices
+ *
+ * 	remembers the frame every peer was last heard from. a
+ * 	peer that has been quiet for longer than the timeout is
+ * 	down, and it is up again as soon as a frame arrives. the
+ * 	event of the wifi adapter for a dropped connection takes
+ * 	the peer of that connection down at once, see the control
+ * 	frames in control.rs of the game crate.
+ */
+
+use crate::engine::MAX_PLAYERS;
+
+pub struct LinkHealth {
+    // loop frame each player was last heard from, None if never
+    last_seen: [Option<u32>; MAX_PLAYERS],
+    timeout: u32,
+}
+
+impl LinkHealth {
+    /// timeout in loop frames
+    pub fn new(timeout: u32) -> Self {
+        LinkHealth {
+            last_seen: [None; MAX_PLAYERS],
+            timeout,
+        }
+    }
+
+    pub fn seen(&mut self, player: usize, now: u32) {
+        if player < MAX_PLAYERS {
+            self.last_seen[player] = Some(now);
+        }
+    }
+
+    /// the connection to the player dropped, it is down until its frames arrive again
+    pub fn lost(&mut self, player: usize) {
+        if player < MAX_PLAYERS {
+            self.last_seen[player] = None;
+        }
+    }
+
+    pub fn is_up(&self, player: usize, now: u32) -> bool {
+        match self.last_seen.get(player) {
+            Some(Some(seen)) => now.wrapping_sub(*seen) < self.timeout,
+            _ => false,
+        }
+    }
+
+    /// the first of the players that is down
+    pub fn waiting_for(&self, players: core::ops::Range<usize>, now: u32) -> Option<usize> {
+        players.into_iter().find(|player| self.is_up(*player, now) == false)
+    }
+}
@@ -0,0 +1,74 @@
+/*
+ * 	controller input shared between the local nunchuk,
+ * 	the remote player data received over UART and the
+ * 	hardware independent game logic
+ */
+
+/// contains the controller data neatly formatted
+#[derive(Copy, Clone)]
+pub struct ControllerInput
+{
+	pub joy_x: i8,
+	pub joy_y: i8,
+	pub accel_x: i16,
+	pub accel_y: i16,
+	pub accel_z: i16,
+	pub btn_z: u8,
+	pub btn_c: u8,
+}
+
+impl ControllerInput
+{
+	pub fn empty() -> Self
+	{
+		ControllerInput {
+			joy_x: 0,
+			joy_y: 0,
+			accel_x: 0,
+			accel_y: 0,
+			accel_z: 0,
+			btn_z: 0,
+			btn_c: 0,
+		}
+	}
+
+	/// the joystick and the buttons as they are sent over the link
+	pub fn serialize(&self) -> [u8; 4]
+	{
+		[self.joy_x as u8, self.joy_y as u8, self.btn_z, self.btn_c]
+	}
+
+	/// the input from the bytes of serialize
+	pub fn deserialize(data: &[u8; 4]) -> Self
+	{
+		ControllerInput {
+			joy_x: data[0] as i8,
+			joy_y: data[1] as i8,
+			btn_z: data[2],
+			btn_c: data[3],
+			..ControllerInput::empty()
+		}
+	}
+
+	/// the nunchuks of the clients report the far ends of the
+	/// joystick on the opposite side, folds them back
+	pub fn folded(self) -> Self
+	{
+		ControllerInput {
+			joy_x: fold(self.joy_x),
+			joy_y: fold(self.joy_y),
+			..self
+		}
+	}
+}
+
+fn fold(joy: i8) -> i8
+{
+	if joy > 100 {
+		-120
+	} else if joy < -100 {
+		120
+	} else {
+		joy
+	}
+}
@@ -0,0 +1,240 @@
+/*
+ * 	round trip time of the link, measured with ping frames
+ *
+ * 	the master sends a ping with an id and its time every
+ * 	PING_INTERVAL frames, the clients send it back as a pong
+ * 	right away. the master keeps the round trip times and
+ * 	the answered pings of the last WINDOW pings for every
+ * 	player. the times are in microseconds and can wrap.
+ *
+ * 	ping and pong payload: id, time (u32, little endian)
+ */
+
+use crate::engine::MAX_PLAYERS;
+
+// loop frames between the pings, a second
+pub const PING_INTERVAL: u32 = 10;
+pub const PING_LEN: usize = 5;
+// pings the statistics are kept for
+const WINDOW: usize = 16;
+// the newest pings can still be on their way, they are not counted as lost.
+// both adapters hold the bytes up to 100 ms and both boards read once a frame
+const IN_FLIGHT: u8 = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Quality {
+    // no ping old enough to be counted was sent yet
+    Unknown,
+    Good,
+    Fair,
+    Poor,
+}
+
+#[derive(Copy, Clone)]
+pub struct LinkQuality {
+    // round trip times of the answered pings, oldest overwritten first
+    rtt: [u32; WINDOW],
+    samples: usize,
+    next: usize,
+    // indexed by ping id % WINDOW
+    answered: [bool; WINDOW],
+    // pings sent to the player, at most WINDOW are remembered
+    sent: u32,
+    last_id: u8,
+}
+
+impl LinkQuality {
+    pub fn new() -> Self {
+        LinkQuality {
+            rtt: [0; WINDOW],
+            samples: 0,
+            next: 0,
+            answered: [false; WINDOW],
+            sent: 0,
+            last_id: 0,
+        }
+    }
+
+    pub fn ping_sent(&mut self, id: u8) {
+        self.answered[id as usize % WINDOW] = false;
+        self.sent += 1;
+        self.last_id = id;
+    }
+
+    /// a pong of the ping id sent at sent_time arrived at now
+    pub fn pong(&mut self, id: u8, sent_time: u32, now: u32) {
+        // Pongs older than the window and repeated ones are ignored
+        let age = self.last_id.wrapping_sub(id) as usize;
+        if self.sent == 0 || age >= WINDOW || age as u32 >= self.sent || self.answered[id as usize % WINDOW] {
+            return;
+        }
+        self.answered[id as usize % WINDOW] = true;
+
+        self.rtt[self.next] = now.wrapping_sub(sent_time);
+        self.next = (self.next + 1) % WINDOW;
+        if self.samples < WINDOW {
+            self.samples += 1;
+        }
+    }
+
+    /// mean round trip time in microseconds
+    pub fn rtt(&self) -> Option<u32> {
+        if self.samples == 0 {
+            return None;
+        }
+        let sum: u64 = self.recent().map(|rtt| rtt as u64).sum();
+        Some((sum / self.samples as u64) as u32)
+    }
+
+    /// mean difference of consecutive round trip times in microseconds
+    pub fn jitter(&self) -> Option<u32> {
+        if self.samples < 2 {
+            return None;
+        }
+        let mut sum: u64 = 0;
+        let mut previous: Option<u32> = None;
+        for rtt in self.recent() {
+            if let Some(p) = previous {
+                sum += (rtt as i64 - p as i64).unsigned_abs();
+            }
+            previous = Some(rtt);
+        }
+        Some((sum / (self.samples as u64 - 1)) as u32)
+    }
+
+    /// percentage of the pings in the window that were not answered
+    pub fn loss(&self) -> u8 {
+        let counted = (self.sent.min(WINDOW as u32) as u8).saturating_sub(IN_FLIGHT);
+        if counted == 0 {
+            return 0;
+        }
+        let lost = (IN_FLIGHT..IN_FLIGHT + counted)
+            .filter(|age| !self.answered[self.last_id.wrapping_sub(*age) as usize % WINDOW])
+            .count();
+        (lost * 100 / counted as usize) as u8
+    }
+
+    pub fn quality(&self) -> Quality {
+        // Pings were sent and none of them came back
+        let loss = self.loss();
+        if loss == 100 {
+            return Quality::Poor;
+        }
+        let rtt = match self.rtt() {
+            Some(rtt) => rtt,
+            None => return Quality::Unknown,
+        };
+        if loss == 0 && rtt < 300_000 {
+            Quality::Good
+        } else if loss <= 25 && rtt < 600_000 {
+            Quality::Fair
+        } else {
+            Quality::Poor
+        }
+    }
+}
+
+// private methods
+impl LinkQuality {
+    // the round trip times from the oldest to the newest
+    fn recent(&self) -> impl Iterator<Item = u32> + '_ {
+        let start = (self.next + WINDOW - self.samples) % WINDOW;
+        (0..self.samples).map(move |i| self.rtt[(start + i) % WINDOW])
+    }
+}
+
+impl Default for LinkQuality {
+    fn default() -> Self {
+        LinkQuality::new()
+    }
+}
+
+/// sends the pings of the master and keeps the statistics of every player
+pub struct Pinger {
+    next_id: u8,
+    quality: [LinkQuality; MAX_PLAYERS],
+}
+
+impl Pinger {
+    pub fn new() -> Self {
+        Pinger {
+            next_id: 0,
+            quality: [LinkQuality::new(); MAX_PLAYERS],
+        }
+    }
+
+    /// the payload of the next ping sent to the players at now
+    pub fn ping(&mut self, players: core::ops::Range<usize>, now: u32) -> [u8; PING_LEN] {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        for player in players {
+            self.quality[player].ping_sent(id);
+        }
+        encode(id, now)
+    }
+
+    /// a pong payload from the player arrived at now
+    pub fn pong(&mut self, player: usize, payload: &[u8], now: u32) {
+        if let (Some(quality), Some((id, time))) = (self.quality.get_mut(player), decode(payload)) {
+            quality.pong(id, time, now);
+        }
+    }
+
+    pub fn quality(&self, player: usize) -> &LinkQuality {
+        &self.quality[player]
+    }
+}
+
+impl Default for Pinger {
+    fn default() -> Self {
+        Pinger::new()
+    }
+}
+
+pub fn encode(id: u8, time: u32) -> [u8; PING_LEN] {
+    let t = time.to_le_bytes();
+    [id, t[0], t[1], t[2], t[3]]
+}
+
+pub fn decode(payload: &[u8]) -> Option<(u8, u32)> {
+    if payload.len() != PING_LEN {
+        return None;
+    }
+    Some((payload[0], u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkQuality, Quality, IN_FLIGHT};
+
+    #[test]
+    fn unknown_before_the_pings_are_counted() {
+        let mut quality = LinkQuality::new();
+        assert_eq!(quality.quality(), Quality::Unknown);
+        for id in 0..IN_FLIGHT {
+            quality.ping_sent(id);
+        }
+        assert_eq!(quality.quality(), Quality::Unknown);
+    }
+
+    #[test]
+    fn poor_when_no_ping_is_answered() {
+        let mut quality = LinkQuality::new();
+        for id in 0..IN_FLIGHT + 1 {
+            quality.ping_sent(id);
+        }
+        assert_eq!(quality.loss(), 100);
+        assert_eq!(quality.quality(), Quality::Poor);
+    }
+
+    #[test]
+    fn good_when_every_ping_is_answered_fast() {
+        let mut quality = LinkQuality::new();
+        for id in 0..8 {
+            quality.ping_sent(id);
+            quality.pong(id, 1000, 51_000);
+        }
+        assert_eq!(quality.rtt(), Some(50_000));
+        assert_eq!(quality.quality(), Quality::Good);
+    }
+}
@@ -0,0 +1,488 @@
+/*
+ * 	input delay lockstep, every device runs the same match
+ *
+ * 	the master starts a session with the rules and the input
+ * 	delay. after that only the inputs go over the link: the
+ * 	input read at tick t is played at tick t + delay on every
+ * 	device, and a device only simulates a tick when it has
+ * 	the inputs of every player for it. the game has no
+ * 	randomness and the physics is deterministic, so the
+ * 	matches stay the same.
+ *
+ * 	every input frame repeats the inputs the other devices
+ * 	have not played yet, so a lost frame is replaced by the
+ * 	next one. the tick a device plays next tells the others
+ * 	what it already has. a checksum of the match is sent
+ * 	every CHECKSUM_INTERVAL ticks to notice a desync.
+ *
+ * 	start:    session, delay, players, max score, rules
+ * 	input:    session, tick of the sender (u32), first tick (u32),
+ * 	          count, (joy_x, joy_y, btn_z, btn_c)[count]
+ * 	checksum: session, tick (u32), checksum (u32)
+ */
+
+use crate::engine::MAX_PLAYERS;
+use crate::input::ControllerInput;
+use crate::matchstate::{ Match, MatchEvents };
+use crate::rules::{ self, GameRules };
+use crate::game::link::MAX_PAYLOAD;
+
+pub const MAX_INPUT_DELAY: u8 = 8;
+pub const CHECKSUM_INTERVAL: u32 = 10;
+pub const START_LEN: usize = 4 + rules::ENCODED_LEN;
+pub const CHECKSUM_LEN: usize = 9;
+
+// ticks of inputs kept, half of them played and half ahead. the other
+// devices are never more than the input delay behind or ahead
+const BUFFER: usize = 64;
+const INPUT_LEN: usize = 4;
+const INPUT_HEADER_LEN: usize = 10;
+// local checksums kept for the ones of the slower devices
+const CHECKSUMS: usize = 4;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Settings {
+    // tells the frames of a restarted match from the old ones
+    pub session: u8,
+    // ticks between reading an input and playing it
+    pub delay: u8,
+    pub player_count: u8,
+    pub max_score: u8,
+    pub rules: GameRules,
+}
+
+impl Settings {
+    pub fn encode(&self) -> [u8; START_LEN] {
+        let mut out = [0u8; START_LEN];
+        out[0] = self.session;
+        out[1] = self.delay;
+        out[2] = self.player_count;
+        out[3] = self.max_score;
+        out[4..].copy_from_slice(&self.rules.encode());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != START_LEN || bytes[1] > MAX_INPUT_DELAY || bytes[2] as usize > MAX_PLAYERS {
+            return None;
+        }
+        Some(Settings {
+            session: bytes[0],
+            delay: bytes[1],
+            player_count: bytes[2],
+            max_score: bytes[3],
+            rules: GameRules::decode(&bytes[4..])?,
+        })
+    }
+}
+
+pub struct Lockstep {
+    settings: Settings,
+    // player id of this device
+    local: usize,
+    // next tick to play
+    tick: u32,
+    // tick and inputs of each player as sent, indexed by tick % BUFFER
+    inputs: [(u32, [Option<[u8; INPUT_LEN]>; MAX_PLAYERS]); BUFFER],
+    // next tick of each player, they have every input before it
+    acked: [u32; MAX_PLAYERS],
+    // players heard from in this session
+    heard: [bool; MAX_PLAYERS],
+    // (tick, checksum) of the last checksum ticks played here
+    checksums: [Option<(u32, u32)>; CHECKSUMS],
+    // checksum of a faster player, for a tick not played here yet
+    pending: [Option<(u32, u32)>; MAX_PLAYERS],
+    // checksum to send to the others
+    unsent: Option<(u32, u32)>,
+    desync: Option<u32>,
+}
+
+impl Lockstep {
+    pub fn new(settings: Settings, local: usize) -> Self {
+        let mut lockstep = Lockstep {
+            settings,
+            local,
+            tick: 0,
+            inputs: [(0, [None; MAX_PLAYERS]); BUFFER],
+            acked: [0; MAX_PLAYERS],
+            heard: [false; MAX_PLAYERS],
+            checksums: [None; CHECKSUMS],
+            pending: [None; MAX_PLAYERS],
+            unsent: None,
+            desync: None,
+        };
+        // Nobody has pressed anything before the first input arrives
+        for tick in 0..settings.delay as usize {
+            lockstep.inputs[tick] = (tick as u32, [Some(ControllerInput::empty().serialize()); MAX_PLAYERS]);
+        }
+        lockstep.heard[local] = true;
+        lockstep
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// the next tick to play
+    pub fn tick(&self) -> u32 {
+        self.tick
+    }
+
+    /// every player has sent inputs for this session
+    pub fn started(&self) -> bool {
+        self.heard[..self.settings.player_count as usize].iter().all(|h| *h)
+    }
+
+    /// the first tick the checksums differed at
+    pub fn desync(&self) -> Option<u32> {
+        self.desync
+    }
+
+    /// schedules the input of this device read at the current tick, once per tick
+    pub fn local_input(&mut self, input: ControllerInput) {
+        let tick = self.tick + self.settings.delay as u32;
+        let local = self.local;
+        let slot = self.slot(tick);
+        // Only what is sent is kept, so every device plays the same input
+        if slot[local].is_none() {
+            slot[local] = Some(input.serialize());
+        }
+    }
+
+    /// the input frame payload with the inputs the others have not played yet
+    pub fn input_payload(&self, out: &mut [u8; MAX_PAYLOAD]) -> usize {
+        let newest = self.tick + self.settings.delay as u32;
+        let first = (0..self.settings.player_count as usize)
+            .filter(|player| *player != self.local)
+            .map(|player| self.acked[player])
+            .min()
+            .unwrap_or(newest)
+            .max(newest.saturating_sub(BUFFER as u32 / 2 - 1))
+            .min(newest);
+
+        out[0] = self.settings.session;
+        out[1..5].copy_from_slice(&self.tick.to_le_bytes());
+        out[5..9].copy_from_slice(&first.to_le_bytes());
+
+        let mut count = 0;
+        let mut len = INPUT_HEADER_LEN;
+        for tick in first..=newest {
+            let input = match self.input(tick, self.local) {
+                Some(input) => input,
+                None => break,
+            };
+            if len + INPUT_LEN > MAX_PAYLOAD {
+                break;
+            }
+            out[len..len + INPUT_LEN].copy_from_slice(&input);
+            len += INPUT_LEN;
+            count += 1;
+        }
+        out[9] = count;
+        len
+    }
+
+    pub fn receive_input(&mut self, player: usize, payload: &[u8]) {
+        if payload.len() < INPUT_HEADER_LEN || payload[0] != self.settings.session
+            || player >= self.settings.player_count as usize || player == self.local {
+            return;
+        }
+        self.heard[player] = true;
+        self.acked[player] = self.acked[player].max(u32_at(payload, 1));
+
+        let first = u32_at(payload, 5);
+        let inputs = payload[INPUT_HEADER_LEN..].chunks(INPUT_LEN).take(payload[9] as usize);
+        for (tick, bytes) in (first..).zip(inputs) {
+            // Inputs already played and ones too far ahead for the buffer are skipped
+            if tick < self.tick || tick >= self.tick + BUFFER as u32 / 2 || bytes.len() != INPUT_LEN {
+                continue;
+            }
+            let slot = self.slot(tick);
+            if slot[player].is_none() {
+                slot[player] = Some([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            }
+        }
+    }
+
+    /// plays the next tick if the inputs of every player have arrived
+    pub fn advance(&mut self, game_match: &mut Match) -> Option<MatchEvents> {
+        let mut inputs = [ControllerInput::empty(); MAX_PLAYERS];
+        let player_count = self.settings.player_count as usize;
+        for (player, input) in inputs.iter_mut().enumerate().take(player_count) {
+            *input = ControllerInput::deserialize(&self.input(self.tick, player)?);
+        }
+
+        // The played inputs stay in the buffer for the slower devices
+        let events = game_match.update(inputs);
+        self.tick += 1;
+
+        if self.tick % CHECKSUM_INTERVAL == 0 {
+            let checksum = (self.tick, game_match.checksum());
+            self.checksums[(self.tick / CHECKSUM_INTERVAL) as usize % CHECKSUMS] = Some(checksum);
+            self.unsent = Some(checksum);
+
+            for player in 0..MAX_PLAYERS {
+                if let Some(remote) = self.pending[player] {
+                    if remote.0 == checksum.0 {
+                        self.pending[player] = None;
+                        self.compare(remote);
+                    }
+                }
+            }
+        }
+        Some(events)
+    }
+
+    /// the checksum frame payload of the last checksum tick, once
+    pub fn take_checksum(&mut self) -> Option<[u8; CHECKSUM_LEN]> {
+        let (tick, checksum) = self.unsent.take()?;
+        let mut out = [0u8; CHECKSUM_LEN];
+        out[0] = self.settings.session;
+        out[1..5].copy_from_slice(&tick.to_le_bytes());
+        out[5..9].copy_from_slice(&checksum.to_le_bytes());
+        Some(out)
+    }
+
+    pub fn receive_checksum(&mut self, player: usize, payload: &[u8]) {
+        if payload.len() != CHECKSUM_LEN || payload[0] != self.settings.session || player >= MAX_PLAYERS {
+            return;
+        }
+        let remote = (u32_at(payload, 1), u32_at(payload, 5));
+        if remote.0 > self.tick {
+            self.pending[player] = Some(remote);
+        } else {
+            self.compare(remote);
+        }
+    }
+}
+
+// private methods
+impl Lockstep {
+    fn input(&self, tick: u32, player: usize) -> Option<[u8; INPUT_LEN]> {
+        let (slot_tick, inputs) = &self.inputs[tick as usize % BUFFER];
+        if *slot_tick != tick {
+            return None;
+        }
+        inputs[player]
+    }
+
+    // the inputs of the tick, an older tick using the slot is forgotten
+    fn slot(&mut self, tick: u32) -> &mut [Option<[u8; INPUT_LEN]>; MAX_PLAYERS] {
+        let slot = &mut self.inputs[tick as usize % BUFFER];
+        if slot.0 != tick {
+            *slot = (tick, [None; MAX_PLAYERS]);
+        }
+        &mut slot.1
+    }
+
+    fn compare(&mut self, remote: (u32, u32)) {
+        let local = self.checksums[(remote.0 / CHECKSUM_INTERVAL) as usize % CHECKSUMS];
+        if let Some(local) = local {
+            if local.0 == remote.0 && local.1 != remote.1 && self.desync.is_none() {
+                self.desync = Some(remote.0);
+            }
+        }
+    }
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+    u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+// Two devices in lockstep over a slow link that loses frames, computer players press
+// the buttons
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::{Lockstep, Settings};
+    use crate::ai::{AiController, Difficulty};
+    use crate::engine::MAX_PLAYERS;
+    use crate::game::link::{Decoder, Encoder, FrameKind, MAX_FRAME, MAX_PAYLOAD};
+    use crate::input::ControllerInput;
+    use crate::matchstate::Match;
+    use crate::rules::GameRules;
+
+    // ten minutes of game
+    const TICKS: u32 = 6000;
+    const SEED: u32 = 7;
+
+    // xorshift, so every run checks the same matches
+    struct Random(u32);
+
+    impl Random {
+        fn next(&mut self) -> u32 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 17;
+            self.0 ^= self.0 << 5;
+            self.0
+        }
+
+        fn below(&mut self, n: u32) -> u32 {
+            self.next() % n
+        }
+    }
+
+    fn settings(rules: GameRules, delay: u8) -> Settings {
+        Settings { session: 1, delay, player_count: 2, max_score: 3, rules }
+    }
+
+    // the checksum after every tick of a match between computers
+    fn play(rules: GameRules, seed: u32) -> Vec<u32> {
+        let mut game_match = Match::new(rules, 3, 2);
+        let mut computers: Vec<AiController> =
+            (0..2).map(|p| AiController::new(p, Difficulty::Normal, seed + p as u32)).collect();
+        let mut checksums = Vec::new();
+        for _ in 0..TICKS {
+            let mut inputs = [ControllerInput::empty(); MAX_PLAYERS];
+            for computer in computers.iter_mut() {
+                inputs[computer.player()] = computer.input(game_match.state());
+            }
+            game_match.update(inputs);
+            checksums.push(game_match.checksum());
+        }
+        checksums
+    }
+
+    struct Device {
+        lockstep: Lockstep,
+        game_match: Match,
+        computer: AiController,
+        encoder: Encoder,
+        decoder: Decoder,
+        // checksum after every tick played
+        checksums: Vec<u32>,
+    }
+
+    impl Device {
+        fn new(settings: Settings, player: usize, rules: GameRules) -> Self {
+            Device {
+                lockstep: Lockstep::new(settings, player),
+                game_match: Match::new(rules, settings.max_score, settings.player_count as usize),
+                computer: AiController::new(player, Difficulty::Normal, SEED + player as u32),
+                encoder: Encoder::new(player as u8),
+                decoder: Decoder::new(),
+                checksums: Vec::new(),
+            }
+        }
+
+        // one frame of the game loop, returns the bytes sent
+        fn frame(&mut self) -> Vec<u8> {
+            let input = self.computer.input(self.game_match.state());
+            self.lockstep.local_input(input);
+
+            let mut bytes = Vec::new();
+            let mut payload = [0u8; MAX_PAYLOAD];
+            let len = self.lockstep.input_payload(&mut payload);
+            self.send(FrameKind::LockstepInput, &payload[..len], &mut bytes);
+
+            if self.lockstep.advance(&mut self.game_match).is_some() {
+                self.checksums.push(self.game_match.checksum());
+            }
+            if let Some(checksum) = self.lockstep.take_checksum() {
+                self.send(FrameKind::LockstepChecksum, &checksum, &mut bytes);
+            }
+            bytes
+        }
+
+        fn send(&mut self, kind: FrameKind, payload: &[u8], bytes: &mut Vec<u8>) {
+            let mut frame = [0u8; MAX_FRAME];
+            let len = self.encoder.encode(kind, payload, &mut frame).unwrap();
+            bytes.extend_from_slice(&frame[..len]);
+        }
+
+        fn receive(&mut self, bytes: &[u8]) {
+            let lockstep = &mut self.lockstep;
+            self.decoder.feed(bytes, |result| {
+                if let Ok(frame) = result {
+                    match frame.kind {
+                        FrameKind::LockstepInput => lockstep.receive_input(frame.source as usize, frame.payload()),
+                        FrameKind::LockstepChecksum => lockstep.receive_checksum(frame.source as usize, frame.payload()),
+                        _ => {}
+                    }
+                }
+            });
+        }
+    }
+
+    // runs two devices over a link that delivers after latency frames and
+    // drops loss_percent of the bursts, returns the devices after frames frames
+    fn lockstep(delay: u8, latency: usize, loss_percent: u32, rules: [GameRules; 2], frames: u32) -> [Device; 2] {
+        let mut random = Random(0x2468_ACE1);
+        let mut devices = [
+            Device::new(settings(rules[0], delay), 0, rules[0]),
+            Device::new(settings(rules[1], delay), 1, rules[1]),
+        ];
+        let mut links: [VecDeque<Vec<u8>>; 2] = [VecDeque::new(), VecDeque::new()];
+
+        for _ in 0..frames {
+            for (device, link) in devices.iter_mut().zip(links.iter_mut()) {
+                let bytes = device.frame();
+                let bytes = if random.below(100) < loss_percent { Vec::new() } else { bytes };
+                link.push_back(bytes);
+            }
+            for (i, link) in links.iter_mut().enumerate() {
+                if link.len() > latency {
+                    let bytes = link.pop_front().unwrap();
+                    devices[1 - i].receive(&bytes);
+                }
+            }
+        }
+        devices
+    }
+
+    // both devices played the same match for a good part of the frames
+    fn in_step(delay: u8, latency: usize, loss_percent: u32) {
+        let devices = lockstep(delay, latency, loss_percent, [GameRules::classic(); 2], TICKS);
+        let played = devices[0].checksums.len().min(devices[1].checksums.len());
+        assert!(played > TICKS as usize / 4, "{} ticks in {} frames", played, TICKS);
+        assert!(devices[0].checksums[..played] == devices[1].checksums[..played]);
+        assert_eq!(devices[0].lockstep.desync(), None);
+        assert_eq!(devices[1].lockstep.desync(), None);
+    }
+
+    // The same inputs always give the same match
+    #[test]
+    fn replay() {
+        let first = play(GameRules::classic(), SEED);
+        assert!(first == play(GameRules::classic(), SEED));
+        assert!(first != play(GameRules::classic(), SEED + 1));
+    }
+
+    // The rules survive the start frame unchanged
+    #[test]
+    fn rules() {
+        let sent = GameRules::chaotic();
+        let received = Settings::decode(&settings(sent, 3).encode()).map(|s| s.rules);
+        assert!(received == Some(sent));
+        assert!(play(received.unwrap(), SEED) == play(sent, SEED));
+    }
+
+    #[test]
+    fn clean() {
+        in_step(4, 0, 0);
+    }
+
+    #[test]
+    fn latency() {
+        in_step(4, 3, 0);
+    }
+
+    #[test]
+    fn lossy() {
+        in_step(4, 3, 20);
+    }
+
+    #[test]
+    fn late_input() {
+        in_step(1, 3, 10);
+    }
+
+    // Devices playing by different rules notice the desync
+    #[test]
+    fn desync() {
+        let devices = lockstep(4, 3, 0, [GameRules::classic(), GameRules::chaotic()], 1000);
+        assert!(devices[0].lockstep.desync().is_some());
+        assert!(devices[1].lockstep.desync().is_some());
+    }
+}
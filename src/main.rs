@@ -7,6 +7,7 @@
 #![feature(min_const_generics)]
 
 pub use gd32vf103xx_hal as hal;
+pub use brm_game as game;
 
 use panic_halt as _;
 use arrayvec::ArrayString;
@@ -41,8 +42,36 @@ pub mod ws2812;
 pub mod gameboard;
 pub mod colors;
 
-pub mod game;
+pub mod input;
+pub mod engine;
+pub mod rules;
+pub mod matchstate;
+pub mod ai;
+pub mod uartrx;
+pub mod screen;
+pub mod stream;
+pub mod health;
+pub mod latency;
+pub mod lockstep;
+pub mod role;
+pub mod handshake;
+pub mod adapter;
 use gameboard::GameBoard;
+use engine::GameState;
+use game::link;
+use game::control::{ self, Event };
+use matchstate::Match;
+use screen::{ Screen, Shade };
+use stream::{ ScreenSender, ScreenReceiver };
+use health::LinkHealth;
+use latency::{ Pinger, Quality };
+use lockstep::Lockstep;
+use role::Role;
+use handshake::{ Ack, Controller, Hello, Peers };
+use input::ControllerInput;
+use rules::GameRules;
+use ai::{ AiController, Difficulty };
+use embedded_hal::digital::v2::{ InputPin, OutputPin };
 use ws2812::{ Ws2812, RGB };
 
 
@@ -56,123 +85,350 @@ const Y_LIMIT: usize = 16;
 
 const OLED_DEBUG_SCREEN: bool = false;
 const SERIAL_DEBUG: bool = false;
-const MASTER_DEVICE: bool = false;
-const DEFAULT_TIMEOUT: u8 = 10;
-const INCOMING_DATA_HEADER: [char;4] = ['D','A','T','A'];
-const INCOMING_DATA_LEN: i8 = 4;
-const NUNCHUK_THRES: i8 = 100; // threshold for moving
-const PROJECTILE_NONE: char = '.';
-const PROJECTILE_P1: char = '*';
-const PROJECTILE_P2: char = '#';
-
-#[derive(Clone)]
-struct Player {
-    x: f32,
-    y: f32,
-    color: RGB,
-    shoot_timeout: u8,
-    shoot_btn: bool,
-    target_x:usize,
-    target_y:usize,
-    use_target: bool,
-    input: nunchuk::ControllerInput,
+// Both devices run the match and only exchange their inputs instead of the master streaming its screen
+const LOCKSTEP: bool = false;
+// Frames between reading an input and playing it in lockstep, covers the round trip time of the link
+const INPUT_DELAY: u8 = 3;
+// Frames the master keeps streaming in lockstep after a spectator was last heard from
+const SPECTATOR_TIMEOUT_FRAMES: u32 = 50;
+const PLAYER_COLORS: [RGB; engine::MAX_PLAYERS] = [colors::PURPLE, colors::OLIVE, colors::TEAL, colors::MAROON];
+// Rounds needed to win the match, also the maximum of the scoreboard
+const MAX_SCORE: u8 = 3;
+// Frames between the resync requests of a client that lost the master screen
+const RESYNC_REQUEST_INTERVAL: u32 = 10;
+// Frames without hearing from a player before the match is paused to wait for them, 2 seconds
+const LINK_TIMEOUT_FRAMES: u32 = 20;
+// Border leds of the master showing the link quality of each client
+const QUALITY_LEDS: [(usize, usize); engine::MAX_PLAYERS] = [(15, 15), (0, 0), (15, 0), (0, 15)];
+// 96-bit unique device id of the GD32VF103, factory programmed
+const UNIQUE_ID_ADDRESS: usize = 0x1FFF_F7E8;
+
+// What the device keeps about the link
+struct LinkState {
+    // by the connection of the wifi adapter the bytes came from
+    decoders: [link::Decoder; control::MAX_CONNS],
+    // the connection the adapter said the next bytes come from
+    conn: usize,
+    // the id accepted in the hello sent over each connection, the master on a client
+    players: [Option<usize>; control::MAX_CONNS],
+    // the screen of the master, on a client
+    receiver: ScreenReceiver,
+    health: LinkHealth,
+    // round trip times of the clients, on the master
+    pinger: Pinger,
+    // the lockstep session being played, in lockstep mode
+    lockstep: Option<Lockstep>,
+    // loop frame a spectator was last heard from, on the master
+    spectator_seen: Option<u32>,
+    // the boards that sent a hello, on the master
+    peers: Peers,
+    // id this board sends its frames with
+    local: u8,
+    // tells this board apart from others sending the same id, on a client
+    board: u32,
+    // the last answer of the master to a hello of this board, on a client
+    answer: Option<Ack>,
 }
 
-impl Default for Player {
-    fn default() -> Self {
-        Player {
-            x: f32::default(),
-            y: f32::default(),
-            color: colors::PURPLE,
-            shoot_timeout: 4,
-            shoot_btn: false,
-            target_x: 0,
-            target_y: 0,
-            use_target: false,
-            // this could also be defaulted
-            input: nunchuk::ControllerInput{
-                joy_x:0,
-                joy_y:0,
-                btn_z:0,
-                btn_c:0,
-                accel_x:0,
-                accel_y:0,
-                accel_z:0
-            }
-        }
-        
-    }
+// What arrived over the link since the last frame
+struct LinkEvents {
+    // input of a remote player, on the master
+    input_received: bool,
+    // a client asked for the whole screen, on the master
+    resync_requested: bool,
+    // the screen of the master changed, on a client
+    screen_changed: bool,
+    // the wifi adapter lost the connections
+    links_down: [bool; control::MAX_CONNS],
+    // the wifi adapter started and waits for the role
+    adapter_started: bool,
+    // the wifi adapter made a new connection
+    connected: bool,
+    // the answers to the hellos by the id of the sender, on the master
+    acks: [Option<Ack>; link::MAX_SOURCES],
+    // a ping of the master to answer, on a client
+    ping: Option<[u8; latency::PING_LEN]>,
+    // the master started a lockstep session, on a client
+    start: Option<lockstep::Settings>,
 }
 
-// Read UART for input containing the remote player joystick etc data
-// Checks for header string "DATA" and reads 4 bytes after that
-// Returns either the full input values or None
-fn read_remote_joy(rx: &mut gd32vf103xx_hal::serial::Rx<gd32vf103xx_hal::pac::USART0>,
-     nunchuk_incoming_data: &mut [u8;4],
-      nunchuk_index: &mut i8,
-      tx: &mut gd32vf103xx_hal::serial::Tx<gd32vf103xx_hal::pac::USART1>,) -> Option<nunchuk::ControllerInput>
+// Drain the bytes received from UART since the last frame for link frames, never
+// waits for more to arrive. Stores the input of every remote player heard from to inputs
+// and applies the screen frames of the master. Every sender is marked seen at frame now,
+// pongs are timed with micros and the control frames of the wifi adapter are turned into events.
+// The bytes of every connection of the adapter go to a decoder of their own
+fn read_link(link_state: &mut LinkState,
+      inputs: &mut [ControllerInput; engine::MAX_PLAYERS],
+      now: u32,
+      micros: u32,
+      tx: &mut gd32vf103xx_hal::serial::Tx<gd32vf103xx_hal::pac::USART1>,) -> LinkEvents
 {
-    let mut full_input_received = false;
-    let mut input = nunchuk::ControllerInput{joy_x:0,joy_y:0,btn_z:0,btn_c:0,accel_x:0,accel_y:0,accel_z:0};
+    let LinkState { decoders, conn, players, receiver, health, pinger, lockstep, spectator_seen, peers, local, board, answer } = link_state;
+    let mut events = LinkEvents { input_received: false, resync_requested: false, screen_changed: false,
+        links_down: [false; control::MAX_CONNS], adapter_started: false, connected: false, acks: [None; link::MAX_SOURCES], ping: None, start: None };
     let mut bytes = 0;
+    let mut chunk = [0u8; 64];
+    let lost: u32 = decoders.iter().map(|decoder| decoder.stats.lost + decoder.stats.corrupted).sum();
 
-    while let Ok(o) = nb::block!(rx.read())
+    loop
     {
-        let byte = Some(o);
-        bytes = bytes +1;
-        //tx.write(byte.unwrap());
-        
+        let count = uartrx::read(&mut chunk);
+        if count == 0
+        {
+            break;
+        }
+        bytes = bytes + count;
 
-        // Waiting for DATA string
-        if *nunchuk_index < 0
+        // A byte at a time, the bytes after a Source event go to the decoder of its connection
+        for byte in chunk[..count].iter()
         {
-            // Iterate through the expected header string "DATA"
-            if byte.unwrap() == INCOMING_DATA_HEADER[((*nunchuk_index)+4) as usize] as u8
-            {
-                *nunchuk_index = *nunchuk_index+1;
-            } else { // Start from the beginning even if one character is wrong                
-                *nunchuk_index = -4;
-            }
-        } else { // Header received
-            // Receive 4 bytes of actual data
-            nunchuk_incoming_data[(*nunchuk_index) as usize] = byte.unwrap();
-            *nunchuk_index = *nunchuk_index+1;
+            let from = *conn;
+            decoders[from].feed(core::slice::from_ref(byte), |result| {
+                let frame = match result {
+                    Ok(frame) => frame,
+                    Err(error) => {
+                        // Debug
+                        write!(tx, "Link error {:?}\r\n", error).expect("failed to create buffer");
+                        return;
+                    }
+                };
+                let player = frame.source as usize;
+                let data = frame.payload();
+                health.seen(player, now);
+
+                match frame.kind {
+                    link::FrameKind::Input => {
+                        // Player 0 is the master itself, ids outside the match, boards without an
+                        // accepted hello and inputs from another connection than the hello are ignored
+                        if data.len() != 4 || player == 0 || player >= peers.player_count() || peers.accepted(player) == false
+                            || players[from] != Some(player)
+                        {
+                            return;
+                        }
 
-            // Check if the last byte is received
-            if *nunchuk_index == INCOMING_DATA_LEN 
-            {
-                // Reset the index at the 4th byte and extract the data to the ControllerInput
-                *nunchuk_index = -4;
-                full_input_received = true;
-                input.joy_x = nunchuk_incoming_data[0] as i8;
-                input.joy_y = nunchuk_incoming_data[1] as i8;
-                input.btn_z = nunchuk_incoming_data[2];
-                input.btn_c = nunchuk_incoming_data[3];
-
-                if input.joy_x > 100 {
-                    input.joy_x = -120;
-                } else if input.joy_x < -100 {
-                    input.joy_x = 120;
-                }
+                        let input = ControllerInput::deserialize(&[data[0], data[1], data[2], data[3]]);
+
+                        // Debug
+                        write!(tx, "Got {}: {} {}\r\n", player, input.joy_x, input.joy_y).expect("failed to create buffer");
 
-                if input.joy_y > 100 {
-                    input.joy_y = -120;
-                } else if input.joy_y < -100 {
-                    input.joy_y = 120;
+                        inputs[player] = input;
+                        events.input_received = true;
+                    }
+                    link::FrameKind::ResyncRequest => events.resync_requested = true,
+                    link::FrameKind::Ping => {
+                        if player == 0 && data.len() == latency::PING_LEN
+                        {
+                            let mut ping = [0u8; latency::PING_LEN];
+                            ping.copy_from_slice(data);
+                            events.ping = Some(ping);
+                        }
+                    }
+                    link::FrameKind::Pong => pinger.pong(player, data, micros),
+                    link::FrameKind::LockstepStart => {
+                        if player == 0
+                        {
+                            events.start = lockstep::Settings::decode(data);
+                        }
+                    }
+                    link::FrameKind::LockstepInput => {
+                        if let Some(lockstep) = lockstep.as_mut()
+                        {
+                            lockstep.receive_input(player, data);
+                        }
+                    }
+                    link::FrameKind::LockstepChecksum => {
+                        if let Some(lockstep) = lockstep.as_mut()
+                        {
+                            lockstep.receive_checksum(player, data);
+                        }
+                    }
+                    link::FrameKind::Spectate => {
+                        // Spectators use the ids after the players up to the one of the adapter,
+                        // they never take part in the match
+                        if player >= engine::MAX_PLAYERS && frame.source != link::ADAPTER_SOURCE
+                        {
+                            *spectator_seen = Some(now);
+                        }
+                    }
+                    link::FrameKind::Hello => {
+                        if let Some(hello) = Hello::decode(data)
+                        {
+                            let ack = peers.hello(player, &hello);
+                            match ack.result
+                            {
+                                Ok(()) => {
+                                    // A board that connected again has left its old connection
+                                    for slot in players.iter_mut().filter(|slot| **slot == Some(player))
+                                    {
+                                        *slot = None;
+                                    }
+                                    players[from] = Some(player);
+                                }
+                                Err(refusal) => {
+                                    // Debug
+                                    write!(tx, "Refused {} build {:08x}: {:?}\r\n", player, hello.build, refusal).expect("failed to create buffer");
+                                }
+                            }
+                            if let Some(slot) = events.acks.get_mut(player)
+                            {
+                                *slot = Some(ack);
+                            }
+                        }
+                    }
+                    link::FrameKind::HelloAck => {
+                        match Ack::decode(data)
+                        {
+                            Some(ack) if player == 0 && ack.answers(*local, *board) => {
+                                *answer = Some(ack);
+                                players[from] = Some(0);
+                            }
+                            _ => {}
+                        }
+                    }
+                    link::FrameKind::Adapter => {
+                        // Only the adapter wired to this board sends them
+                        if frame.source != link::ADAPTER_SOURCE
+                        {
+                            return;
+                        }
+                        match Event::decode(data)
+                        {
+                            Some(Event::LinkUp(_)) => events.connected = true,
+                            Some(Event::LinkDown(id)) => {
+                                if let Some(down) = events.links_down.get_mut(id as usize)
+                                {
+                                    *down = true;
+                                }
+                            }
+                            Some(Event::Boot) => events.adapter_started = true,
+                            // The frames after it came from another connection
+                            Some(Event::Source(id)) => {
+                                if (id as usize) < control::MAX_CONNS
+                                {
+                                    *conn = id as usize;
+                                }
+                            }
+                            None => {}
+                            Some(event) => {
+                                // Debug
+                                write!(tx, "Adapter {:?}\r\n", event).expect("failed to create buffer");
+                            }
+                        }
+                    }
+                    link::FrameKind::ScreenDelta | link::FrameKind::ScreenResync => {
+                        // Only the master sends its screen
+                        if player == 0 && receiver.apply(&frame) == true
+                        {
+                            events.screen_changed = true;
+                        }
+                    }
                 }
+            });
+        }
+    }
 
-                // Debug
-                write!(tx, "Got {} {}\r\n", input.joy_x, input.joy_y).expect("failed to create buffer");
+    // A missing or broken frame can be a delta of the screen, wait for the next full copy
+    if decoders.iter().map(|decoder| decoder.stats.lost + decoder.stats.corrupted).sum::<u32>() != lost
+    {
+        receiver.lost_sync();
+    }
 
+    // The event can arrive after frames sent before the connection dropped, only the
+    // player of that connection is down
+    for id in 0..control::MAX_CONNS
+    {
+        if events.links_down[id] == false
+        {
+            continue;
+        }
+        write!(tx, "Link {} down\r\n", id).expect("failed to create buffer");
+        if let Some(player) = players[id].take()
+        {
+            health.lost(player);
+            // Another board can take the id of a player that went away
+            peers.forget(player);
+            if player == 0
+            {
+                receiver.lost_sync();
             }
         }
-        
+        // The other end can have restarted its sequence numbers
+        decoders[id].reset();
     }
     write!(tx, "Read: {}\r\n", bytes).expect("failed to create buffer");
-    if full_input_received {
-        return Some(input);
+    return events;
+}
+
+// The screen of the master while the match waits for the player
+fn waiting_screen(peers: &Peers, player: usize, frame: u32) -> Screen
+{
+    match peers.refusal(player)
+    {
+        Some(_) => Screen::refused(player),
+        None => Screen::waiting(player, frame),
+    }
+}
+
+// Sends one link frame to the wifi adapter
+fn send_frame(encoder: &mut link::Encoder, kind: link::FrameKind, payload: &[u8],
+      tx: &mut gd32vf103xx_hal::serial::Tx<gd32vf103xx_hal::pac::USART0>)
+{
+    let mut bytes = [0u8; link::MAX_FRAME];
+    let len = encoder.encode(kind, payload, &mut bytes).expect("failed to encode");
+    for i in 0..len {
+        nb::block!(tx.write(bytes[i])).expect("failed to write");
+    }
+}
+
+fn quality_color(quality: Quality) -> RGB
+{
+    match quality {
+        Quality::Unknown => colors::BLACK,
+        Quality::Good => colors::AQUA,
+        Quality::Fair => colors::YELLOW,
+        Quality::Poor => colors::RED,
+    }
+}
+
+// The unique id of the chip folded into the board id of the hello
+fn board_id() -> u32
+{
+    let mut id = 0;
+    for i in 0..3
+    {
+        // Safe to read, the id is in flash and never changes
+        id ^= unsafe { core::ptr::read_volatile((UNIQUE_ID_ADDRESS as *const u32).add(i)) };
+    }
+    return id;
+}
+
+// Microseconds since boot, wraps after about 71 minutes
+fn micros(clock_speed: u32) -> u32
+{
+    (riscv::register::mcycle::read64() / (clock_speed / 1_000_000) as u64) as u32
+}
+
+fn shade_color(shade: Shade) -> RGB
+{
+    match shade {
+        Shade::Empty => colors::BLACK,
+        Shade::Owner(player) => PLAYER_COLORS[player as usize % engine::MAX_PLAYERS],
+        Shade::Projectile => colors::NAVY,
+        Shade::Target => colors::RED,
+        Shade::Player => colors::YELLOW,
+        Shade::Highlight => colors::GREEN,
     }
-    return None;
+}
+
+// Draws the screen inside the borders to the led matrix
+fn paint<T: OutputPin>(screen: &Screen, board: &mut GameBoard<'_, T>)
+{
+    for (x, y, shade) in screen.cells() {
+        board.set_color(x, y, shade_color(shade));
+    }
+
+    // Update the whole display
+    board.update_matrix();
 }
 
 #[entry]
@@ -217,11 +473,12 @@ fn main() -> ! {
         &mut afio,
         &mut rcu,
     );
-    //serial.listen(gd32vf103xx_hal::serial::Event::Rxne);
-
-    let (mut tx, mut rx) = serial.split();
+    let (mut tx, rx) = serial.split();
     let (mut tx2, rx2) = serial2.split();
 
+    // The USART0 interrupt fills a buffer the game loop drains once per frame
+    uartrx::listen(rx);
+
     if SERIAL_DEBUG == true
     {
         write!(tx,"Starting..\r\n").expect("failed to create buffer");
@@ -252,13 +509,57 @@ fn main() -> ! {
     let sda = gpiob.pb9.into_alternate_open_drain();
     let mut nchuck = nunchuk::Nunchuk::new(&mut afio, &mut rcu, i2c0, scl, sda);
 
-    let player_1 = Player { x: 7.0f32, y:1.0f32, color:colors::PURPLE, ..Default::default()};
-    let player_2 = Player { x: 8.0f32, y:14.0f32, color:colors::OLIVE, ..player_1};
-
-    let mut players = [player_1, player_2];
-
-    let mut objects: [Option<game::MovingObject>; game::MAXIMUM_OBJECTS] = [None; game::MAXIMUM_OBJECTS];
-    let mut number_of_objects: usize = 0;
+    // Pick the rules from the buttons held while booting:
+    // C for fast, Z for chaotic, none for the classic game. both switch the role
+    let boot_input = nchuck.get_input();
+    let switch_role = boot_input.btn_c == 1 && boot_input.btn_z == 1;
+    let rules = if switch_role == true {
+        GameRules::classic()
+    } else if boot_input.btn_c == 1 {
+        GameRules::fast()
+    } else if boot_input.btn_z == 1 {
+        GameRules::chaotic()
+    } else {
+        GameRules::classic()
+    };
+
+    // The joystick held while booting starts a single player game against the computer:
+    // left for easy, up or down for normal, right for hard
+    let difficulty = if boot_input.joy_x < -rules.joystick_threshold {
+        Some(Difficulty::Easy)
+    } else if boot_input.joy_x > rules.joystick_threshold {
+        Some(Difficulty::Hard)
+    } else if boot_input.joy_y > rules.joystick_threshold || boot_input.joy_y < -rules.joystick_threshold {
+        Some(Difficulty::Normal)
+    } else {
+        None
+    };
+    let mut computer = difficulty.map(|d| AiController::new(1, d, riscv::register::mcycle::read() as u32));
+    // A strap to ground picks the role, otherwise the one saved in flash is used
+    let master_strap = gpiob.pb12.into_pull_up_input();
+    let client_strap = gpiob.pb13.into_pull_up_input();
+    let spectator_strap = gpiob.pb14.into_pull_up_input();
+    let role = role::select(
+        &periph.FMC,
+        master_strap.is_low().unwrap_or(false),
+        client_strap.is_low().unwrap_or(false),
+        spectator_strap.is_low().unwrap_or(false),
+        switch_role,
+    );
+    // Tell the adapter whether to start the access point or to connect to it
+    adapter::send(&mut tx, role.announcement()).expect("failed to create buffer");
+    // Single player runs the game logic locally without the wifi link
+    let master = matches!(role, Role::Master(_)) || computer.is_some();
+    let spectator = matches!(role, Role::Spectator(_)) && computer.is_none();
+
+    // The computer opponent always plays a two player match
+    let player_count = match role {
+        Role::Master(players) if computer.is_none() => players as usize,
+        _ => 2,
+    };
+    write!(tx2, "Role {:?}\r\n", role).expect("failed to create buffer");
+    let mut game_match = Match::new(rules, MAX_SCORE, player_count);
+    let mut inputs = [ControllerInput::empty(); engine::MAX_PLAYERS];
 	
     delay.delay_ms(10);
 
@@ -280,30 +581,77 @@ fn main() -> ! {
     //flush board 
     board.flush();
 
+    // Show the role for a moment: a stripe in the color of every player of the match for the
+    // master, the color of its player for a client and white for a spectator
+    for shown in [true, false].iter()
+    {
+        for y in 1..Y_LIMIT-1
+        {
+            for x in 1..X_LIMIT-1
+            {
+                let color = match role {
+                    _ if *shown == false => colors::BLACK,
+                    Role::Master(_) => PLAYER_COLORS[(x - 1) * player_count / (X_LIMIT - 2)],
+                    Role::Client(id) => PLAYER_COLORS[id as usize],
+                    Role::Spectator(_) => colors::WHITE,
+                };
+                board.set_color(x, y, color);
+            }
+        }
+        board.update_matrix();
+        delay.delay_ms(500);
+    }
+
     let mut sboard_pin = gpiob.pb6.into_push_pull_output();
 
     // second argument is the maximum score
-    let sboard = ScoreBoard::new(&mut sboard_pin, 5);
+    let mut sboard = ScoreBoard::new(&mut sboard_pin, MAX_SCORE);
 
     delay.delay_ms(100);
 
     // Temporary buffers for storing incoming nunchuk data
     let mut nunchuk_data: [u8;4];
-    let mut nunchuk_incoming_data: [u8;4] = [0;4];
-    let mut nunchuk_index: i8 = -4;
+    let mut link_state = LinkState {
+        decoders: [link::Decoder::new(), link::Decoder::new(), link::Decoder::new(), link::Decoder::new()],
+        conn: 0,
+        players: [None; control::MAX_CONNS],
+        receiver: ScreenReceiver::new(),
+        health: LinkHealth::new(LINK_TIMEOUT_FRAMES),
+        pinger: Pinger::new(),
+        lockstep: None,
+        spectator_seen: None,
+        peers: Peers::new(player_count),
+        local: 0,
+        board: board_id(),
+        answer: None,
+    };
+    // The master sends the screen as player 0
+    let source = if master == true { 0 } else { role.id() };
+    link_state.local = source;
+    let mut encoder = link::Encoder::new(source);
+    let controller = if spectator == true { Controller::Nothing } else { Controller::Nunchuk };
+    let mut payload = [0u8; link::MAX_PAYLOAD];
+    let mut sender = ScreenSender::new();
+    // Player the paused match is waiting for
+    let mut waiting: Option<usize> = None;
+    // Lockstep needs every device to hear the others, the adapter only connects two.
+    // a spectator gets the screen of the master in both modes
+    let lockstep_mode = LOCKSTEP == true && computer.is_none() && player_count == 2 && spectator == false;
+    let mut session: u8 = 0;
+    let mut frame: u32 = 0;
 
     loop
     {
-        players[0].input = nchuck.get_input();        
-        // Read other player's nunchuk data from UART, if available
-        let remote_data = read_remote_joy(&mut rx, &mut nunchuk_incoming_data, &mut nunchuk_index, &mut tx2);        
-        if remote_data.is_none() == false
+        inputs[0] = nchuck.get_input();
+        // Folded where it is read, so the streamed and the lockstep match play the same input
+        if master == false
         {
-            players[1].input = remote_data.unwrap();
-            board.set_color(0,0, colors::BLACK);
+            inputs[0] = inputs[0].folded();
         }
-        
-        let mut input = players[0].input;
+        // Read other players' nunchuk data from UART, if available
+        let link_events = read_link(&mut link_state, &mut inputs, frame, micros(clock_speed), &mut tx2);
+
+        let input = inputs[0];
 
         if OLED_DEBUG_SCREEN == true
         {
@@ -327,282 +675,307 @@ fn main() -> ! {
             Text::new(&display_buffer_accel, Point::new(10, 50))
                 .into_styled(style)
                 .draw(&mut lcd).unwrap();
+
+            // print out the link quality of the first client
+            if master == true
+            {
+                let quality = link_state.pinger.quality(1);
+                let mut display_buffer_link = ArrayString::<[_; 32]>::new();
+                write!(&mut display_buffer_link, "rtt: {}ms jit: {} loss: {}%  ",
+                    quality.rtt().unwrap_or(0) / 1000, quality.jitter().unwrap_or(0) / 1000, quality.loss()).expect("failed to create buffer");
+                Text::new(&display_buffer_link, Point::new(10, 70))
+                    .into_styled(style)
+                    .draw(&mut lcd).unwrap();
+            }
         }
         if SERIAL_DEBUG == true
         {
             write!(tx,"joy_x: {}: joy_y: {}  \r\n", input.joy_x, input.joy_y).expect("failed to create buffer");
             write!(tx,"btn_z: {}: btn_c: {}  \r\n", input.btn_z, input.btn_c).expect("failed to create buffer");
             //write!(tx,"az: {}: ay: {} az: {}  \r\n", input.accel_x, input.accel_y, input.accel_z).expect("failed to create buffer");
-        }
 
-        // Master device handles the game logic and drawing to the screen
-        if MASTER_DEVICE == true {
+            let errors = uartrx::errors();
+            write!(tx2,"rx overrun: {} framing: {} noise: {} dropped: {}\r\n",
+                errors.overrun, errors.framing, errors.noise, errors.dropped).expect("failed to create buffer");
 
-            // Apply the game physics
-            if number_of_objects > 0 {
-                game::game_tick(&mut objects, number_of_objects);
+            for player in 1..player_count {
+                let quality = link_state.pinger.quality(player);
+                write!(tx2,"p{} rtt: {:?} us jitter: {:?} us loss: {}% {:?}\r\n", player,
+                    quality.rtt(), quality.jitter(), quality.loss(), quality.quality()).expect("failed to create buffer");
             }
-            game::clear_board();
-
-            for i in 0..number_of_objects {
-                let mut object = match objects[i] {
-                    Some(o) => o,
-                    None => continue,
-                };
+        }
 
-                let pos = object.position();
-                
-                // "Explode" objects that have stopped
-                if object.moving() == false || object.get_age() > 100 {                    
-                    for obj_x in pos.0-2..=pos.0+2
-                    {
-                        for obj_y in pos.1-2..=pos.1+2
-                        {
-                            if obj_x > 0 && obj_x < (game::BOARD_WIDTH - 1) &&
-                                obj_y > 0 && obj_y < (game::BOARD_WIDTH - 1)
-                            {
-                                if object.symbol == PROJECTILE_P1
-                                {
-                                    board.set_color(obj_x, obj_y, players[0].color);
-                                } else {
-                                    board.set_color(obj_x, obj_y, players[1].color);
-                                }
-                            }
-                        }
-                    }
-                    objects[i] = None;
-                } else { // Object not stopped yet, add to the game logic output
-                    if pos.0 < 1 || pos.1 < 1 || pos.0 > game::BOARD_WIDTH - 1 || pos.1 > game::BOARD_WIDTH - 1 {
-                        // ToDo: do something with out-of-bounds object
-                    } else {
-                        unsafe {                    
-                            game::BOARD[pos.0 + pos.1 * game::BOARD_WIDTH] = object.symbol;
-                        }
-                        object.add_age();
-                    }
+        // A board that timed out can come back restarted, over udp no LinkDown frees its id
+        if master == true
+        {
+            for player in 1..player_count
+            {
+                if link_state.health.is_up(player, frame) == false
+                {
+                    link_state.peers.forget(player);
                 }
             }
+        }
 
-            // Game logic processing done, parse through the list to find object positions from the output
-            for y in 1..(game::BOARD_WIDTH - 1) {
-                for x in 1..(game::BOARD_WIDTH - 1) {
-                    unsafe {
-                        if game::BOARD[x + y * game::BOARD_WIDTH] != PROJECTILE_NONE 
-                        {
-                            board.set_color_in_buffer(x, y, colors::NAVY);
-                        }
-                    }
-                }
+        // The game waits for the players that have not been heard from in a while,
+        // the master for the clients and a client for the master
+        let now_waiting = if computer.is_some() {
+            None
+        } else if master == true {
+            // A client also has to be accepted with a hello before the match goes on
+            link_state.health.waiting_for(1..player_count, frame)
+                .or(link_state.peers.waiting_for(1..player_count))
+        } else {
+            link_state.health.waiting_for(0..1, frame)
+        };
+        if now_waiting.is_some() && waiting.is_none()
+        {
+            // The other end can have restarted its sequence numbers
+            for decoder in link_state.decoders.iter_mut()
+            {
+                decoder.reset();
             }
+            link_state.receiver.lost_sync();
+        }
+        waiting = now_waiting;
 
-            // Iterate both players
-            for i in 0..=1
+        // An adapter that restarted has forgotten the role
+        if (waiting.is_some() && frame % RESYNC_REQUEST_INTERVAL == 0) || link_events.adapter_started == true
+        {
+            adapter::send(&mut tx, role.announcement()).expect("failed to create buffer");
+        }
+
+        // Clients say hello once a second and as soon as the adapter connected,
+        // the master answers every hello
+        if master == true
+        {
+            for ack in link_events.acks.iter().flatten()
             {
-                input = players[i].input;
+                send_frame(&mut encoder, link::FrameKind::HelloAck, &ack.encode(), &mut tx);
+            }
+        }
+        else if frame % handshake::HELLO_INTERVAL == 0 || link_events.connected == true
+        {
+            send_frame(&mut encoder, link::FrameKind::Hello, &Hello::new(controller, link_state.board).encode(), &mut tx);
+        }
+        let refused = link_state.answer.map_or(false, |ack| ack.result.is_err());
 
-                // If we are waiting after shooting or moving
-                if players[i].shoot_timeout > 0
+        if lockstep_mode == true
+        {
+            // The master starts a new session at boot and after the matches went out of step
+            if master == true && link_state.lockstep.as_ref().map_or(true, |l| l.desync().is_some())
+            {
+                session = session.wrapping_add(1);
+                let settings = lockstep::Settings {
+                    session,
+                    delay: INPUT_DELAY,
+                    player_count: player_count as u8,
+                    max_score: MAX_SCORE,
+                    rules,
+                };
+                link_state.lockstep = Some(Lockstep::new(settings, 0));
+                game_match = Match::new(rules, MAX_SCORE, player_count);
+            }
+            // A client plays the sessions the master starts
+            if let Some(settings) = link_events.start
+            {
+                if link_state.lockstep.as_ref().map_or(true, |l| l.settings().session != settings.session)
                 {
-                    players[i].shoot_timeout = players[i].shoot_timeout-1;
-                } else if players[i].shoot_btn == true { // Trigger is pressed down, draw the targeting indicator
-
-                    // Calculate target direction
-                    let x_float:f32 = input.joy_x as f32;
-                    let y_float:f32 = input.joy_y as f32;
-                    let len:f32 = game::fast_sqrt(game::pow2(game::abs(x_float)) + game::pow2(game::abs(y_float)));
-
-                    // Shoot when player button was pressed and now released
-                    if input.btn_z == 0 {
-                        players[i].shoot_timeout = DEFAULT_TIMEOUT;
-                        players[i].shoot_btn = false;
-                        players[i].use_target = false;
-
-                        // Add offset to the projectile to compensate the game logic
-                        let xpos = players[i].x - 1.0f32;
-                        let ypos = players[i].y - 1.0f32;
+                    link_state.lockstep = Some(Lockstep::new(settings, source as usize));
+                    game_match = Match::new(settings.rules, settings.max_score, settings.player_count as usize);
+                }
+            }
 
-                        let xdir = (x_float / len) * 2.0f32;
-                        let ydir = (y_float / len) * 2.0f32;
+            if let Some(lockstep) = link_state.lockstep.as_mut()
+            {
+                // Announce the session until every client has sent inputs for it
+                if master == true && lockstep.started() == false && frame % latency::PING_INTERVAL == 0
+                {
+                    send_frame(&mut encoder, link::FrameKind::LockstepStart, &lockstep.settings().encode(), &mut tx);
+                }
 
-                        // Iterate through the existing object list to find first empty space to push new
-                        for ii in 0..(game::MAXIMUM_OBJECTS - 1) {
+                // Every frame repeats the inputs the others have not played yet
+                lockstep.local_input(inputs[0]);
+                let len = lockstep.input_payload(&mut payload);
+                send_frame(&mut encoder, link::FrameKind::LockstepInput, &payload[..len], &mut tx);
 
-                            if objects[ii].is_none() || objects[ii].unwrap().symbol == PROJECTILE_NONE {
+                // A tick is only played when the inputs of every player have arrived
+                if lockstep.advance(&mut game_match).is_some()
+                {
+                    // The scoreboard strip shows the rounds won by this player
+                    let wins = game_match.wins()[source as usize];
+                    if wins != sboard.score
+                    {
+                        sboard.set_score(wins);
+                    }
+                }
+                if let Some(checksum) = lockstep.take_checksum()
+                {
+                    send_frame(&mut encoder, link::FrameKind::LockstepChecksum, &checksum, &mut tx);
+                }
+                if let Some(tick) = lockstep.desync()
+                {
+                    write!(tx2, "Desync at tick {}\r\n", tick).expect("failed to create buffer");
+                }
+            }
 
-                                // Select correct symbol to know which player was shooting
-                                let mut symbol = PROJECTILE_P1;
-                                if i == 1
-                                {
-                                    symbol = PROJECTILE_P2;
-                                }
+            let screen = match waiting {
+                _ if refused == true => Screen::refused(link_state.local as usize),
+                Some(player) => waiting_screen(&link_state.peers, player, frame),
+                None => Screen::of_match(&game_match),
+            };
+            paint(&screen, &mut board);
 
-                                let object = game::MovingObject::new(
-                                        game::Vector { x: xpos, y: ypos },
-                                        game::Vector { x: xdir, y: ydir },
-                                        symbol);
-                                objects[ii] = Some(object);
-                                if ii + 1 > number_of_objects
-                                {
-                                    number_of_objects = ii + 1;
-                                }
-                                break;
-                            }
-                        }
-
-                    } else { // Draw target vector if player still holding the trigger
-                        
-                        let xpos = (players[i].x + (x_float / len) * 3.0f32 ) as i32;
-                        let ypos = (players[i].y + (y_float / len) * 3.0f32 ) as i32;
+            // The clients run the match themselves, the screen is only streamed to spectators
+            let watched = link_state.spectator_seen.map_or(false, |seen| frame.wrapping_sub(seen) < SPECTATOR_TIMEOUT_FRAMES);
+            if master == true && watched == true
+            {
+                if link_events.resync_requested == true
+                {
+                    sender.request_resync();
+                }
+                let (kind, len) = sender.next(&screen, &mut payload);
+                send_frame(&mut encoder, kind, &payload[..len], &mut tx);
+            }
 
-                        // Make sure the target is not outside the boundaries
-                        if xpos > 0 && ypos > 0 && xpos < (X_LIMIT-1) as i32 && ypos < (Y_LIMIT-1) as i32 {
-                            players[i].target_x = xpos as usize;
-                            players[i].target_y = ypos as usize;
-                            players[i].use_target = true;
-                        }
+            // The round trip time tells how long the input delay has to be
+            if master == true && frame % latency::PING_INTERVAL == 0
+            {
+                let ping = link_state.pinger.ping(1..player_count, micros(clock_speed));
+                send_frame(&mut encoder, link::FrameKind::Ping, &ping, &mut tx);
+            }
+            if let Some(ping) = link_events.ping
+            {
+                send_frame(&mut encoder, link::FrameKind::Pong, &ping, &mut tx);
+            }
+        }
+        // Master device handles the game logic and drawing to the screen
+        else if master == true {
+            // The computer plays the second player instead of the remote device
+            if let Some(computer) = computer.as_mut() {
+                inputs[1] = computer.input(game_match.state());
+            } else {
+                // Forget the last input of a lost player so they do not keep moving
+                for player in 1..player_count {
+                    if link_state.health.is_up(player, frame) == false {
+                        inputs[player] = ControllerInput::empty();
                     }
-                } else { // If player is not waiting and not pressing the trigger yet
-                    if input.btn_z == 1 {
-                        players[i].shoot_btn = true;
-                    } else if input.joy_x > NUNCHUK_THRES ||
-                              input.joy_x < -NUNCHUK_THRES ||
-                              input.joy_y > NUNCHUK_THRES ||
-                              input.joy_y < -NUNCHUK_THRES {
-                        // Move player when joystick (range -128..127) over NUNCHUK_THRES
-                        let mut moved:bool = false;
-                        let mut x_dir:i8 = 0;
-                        let mut y_dir:i8 = 0;
-
-                        // Check if moving on X-axis
-                        if input.joy_x > NUNCHUK_THRES {
-                            x_dir = 1;
-                        } else if input.joy_x < -NUNCHUK_THRES {
-                            x_dir = -1;
-                        }
-
-                        // Check if moving on Y-axis
-                        if input.joy_y > NUNCHUK_THRES {
-                            y_dir = 1;
-                        } else if input.joy_y < -NUNCHUK_THRES {
-                            y_dir = -1;
-                        }
+                }
+            }
 
-                        // If we are moving make sure we are not over the boundaries in any direction
-                        if x_dir != 0 || y_dir != 0 {
-                            moved = true;
-                            if (players[i].x as i8)+x_dir > 0 && (players[i].x as i8)+x_dir < (X_LIMIT-1) as i8
-                            {
-                                players[i].x += x_dir as f32;
-                            }
-                            if (players[i].y as i8)+y_dir > 0 && (players[i].y as i8)+y_dir < (Y_LIMIT-1)  as i8
-                            {
-                                players[i].y += y_dir as f32;
-                            }
-                        }
-                        
-                        // Add "shoot timeout" also after moving
-                        if moved == true {
-                            players[i].shoot_timeout = DEFAULT_TIMEOUT>>1;
-                        }
+            // The match is paused while waiting, it resumes as soon as the player is heard from
+            let screen = match waiting {
+                Some(player) => waiting_screen(&link_state.peers, player, frame),
+                None => {
+                    let events = game_match.update(inputs);
 
+                    // The scoreboard strip of the master shows the rounds won by the local player
+                    if events.new_match == true {
+                        sboard.delete_score(sboard.score);
                     }
+                    if events.round_won == Some(0) {
+                        sboard.add_score(1);
+                    }
+                    Screen::of_match(&game_match)
                 }
-                
-                // If targeting was enabled, draw the actual red target to screen
-                if players[i].use_target == true 
-                {
-                    board.set_color_in_buffer(players[i].target_x, players[i].target_y, colors::RED);
-                }
+            };
 
-                // Draw the player to the screen without adding to the game board
-                board.set_color_in_buffer(players[i].x as usize, players[i].y as usize, colors::YELLOW);
+            // The border corners show the link quality of each client
+            if computer.is_none()
+            {
+                for player in 1..player_count {
+                    let (x, y) = QUALITY_LEDS[player];
+                    board.set_color(x, y, quality_color(link_state.pinger.quality(player).quality()));
+                }
             }
+            paint(&screen, &mut board);
 
-            // Update the whole display
-            board.update_matrix();
-            board.flush_to_buffer();
+            // Stream the screen to the clients, single player has nobody to send to
+            if computer.is_none()
+            {
+                if link_events.resync_requested == true
+                {
+                    sender.request_resync();
+                }
+                let (kind, len) = sender.next(&screen, &mut payload);
+                send_frame(&mut encoder, kind, &payload[..len], &mut tx);
 
-            // Draw a trail after the projectiles using the shooting player color
-            for i in 0..number_of_objects {
-                let object = match objects[i] {
-                    Some(o) => o,
-                    None => continue,
-                };
-                let pos = object.position();
-                if object.symbol == '*'
+                // Measure the round trip time to the clients once a second
+                if frame % latency::PING_INTERVAL == 0
                 {
-                    board.set_color(pos.0 as usize, pos.1 as usize, players[0].color);
-                } else {
-                    board.set_color(pos.0 as usize, pos.1 as usize, players[1].color);
-                }                    
+                    let ping = link_state.pinger.ping(1..player_count, micros(clock_speed));
+                    send_frame(&mut encoder, link::FrameKind::Ping, &ping, &mut tx);
+                }
             }
-        } else { // Client device only sends the current nunchuk data to the master
-
-            nunchuk_data = nchuck.serialize();
+        } else { // Client device sends the current nunchuk data to the master and shows its screen
 
-            // Send data to the master
-            write!(tx,"DATA").expect("failed to create buffer");
-            delay.delay_ms(1);
-            for i in 0..4 {
-                nb::block!(tx.write(nunchuk_data[i])).expect("failed to write");
+            if spectator == true
+            {
+                // A spectator only tells the master it is watching, once a second
+                if frame % RESYNC_REQUEST_INTERVAL == 0
+                {
+                    send_frame(&mut encoder, link::FrameKind::Spectate, &[], &mut tx);
+                }
             }
-            write!(tx,"END\n").expect("failed to create buffer");
-        }
+            else if refused == false
+            {
+                nunchuk_data = inputs[0].serialize();
 
-        //
-        // Calculate score
-        //
-        const TOTAL_PIXELS: u8 = 14*14;
-        let mut score: [u8;2] = [0;2];
-        for y in 1..(game::BOARD_WIDTH - 1) {
-            for x in 1..(game::BOARD_WIDTH - 1) {
-                let color = board.get_color(x, y);
-                if color == players[0].color
-                {
-                    score[0] = score[0] + 1;
-                } 
-                if color == players[1].color
+                // Send data to the master in an input frame, the player id is the source
+                send_frame(&mut encoder, link::FrameKind::Input, &nunchuk_data, &mut tx);
+
+                // Answer the ping of the master right away
+                if let Some(ping) = link_events.ping
                 {
-                    score[1] = score[1] + 1;
-                } 
+                    send_frame(&mut encoder, link::FrameKind::Pong, &ping, &mut tx);
+                }
             }
-        }
 
-        // Ending condition, two colors cover the whole board
-        if score[0]+score[1] == TOTAL_PIXELS
-        {
-            let color_end: RGB;
-
-            // Check for the winned and select the color
-            if score[0] > score[1] {
-                color_end = players[0].color;
-            } else if score[0] < score[1] {
-                color_end = players[1].color;
-            } else { // Tie
-                color_end = colors::GREEN;
+            // Ask for the whole screen after joining and after missing frames of the master
+            if link_state.receiver.synced() == false && frame % RESYNC_REQUEST_INTERVAL == 0
+            {
+                send_frame(&mut encoder, link::FrameKind::ResyncRequest, &[], &mut tx);
             }
 
-            // Blink the winner color until reset
-            loop {                
-                for y in 1..(game::BOARD_WIDTH - 1) {
-                    for x in 1..(game::BOARD_WIDTH - 1) {
-                        board.set_color_in_buffer(x, y, color_end);
-                    }
+            if refused == true
+            {
+                // The master does not play with this board, the hello is sent again in case it changes
+                paint(&Screen::refused(link_state.local as usize), &mut board);
+            }
+            else if waiting.is_some()
+            {
+                // Nothing from the master, the screen is sent again when it is back
+                paint(&Screen::waiting(0, frame), &mut board);
+            }
+            else if link_events.screen_changed == true
+            {
+                let screen = link_state.receiver.screen();
+                paint(screen, &mut board);
+                // The scoreboard strip shows the rounds won by this player, or by the leader on a spectator
+                let wins = if spectator == true {
+                    screen.wins.iter().copied().max().unwrap_or(0)
+                } else {
+                    screen.wins[source as usize]
+                };
+                if wins != sboard.score
+                {
+                    sboard.set_score(wins);
                 }
-                board.update_matrix();
-                board.flush_to_buffer();
-                delay.delay_ms(1000);
-                for y in 1..(game::BOARD_WIDTH - 1) {
-                    for x in 1..(game::BOARD_WIDTH - 1) {
-                        board.set_color_in_buffer(x, y, colors::BLACK);
-                    }
+
+                // The lcd of a spectator shows the rounds won by every player
+                if spectator == true
+                {
+                    let mut display_buffer_wins = ArrayString::<[_; 26]>::new();
+                    write!(&mut display_buffer_wins, "wins: {} {} {} {}  ",
+                        screen.wins[0], screen.wins[1], screen.wins[2], screen.wins[3]).expect("failed to create buffer");
+                    Text::new(&display_buffer_wins, Point::new(10, 10))
+                        .into_styled(style)
+                        .draw(&mut lcd).unwrap();
                 }
-                board.update_matrix();
-                board.flush_to_buffer();
-                delay.delay_ms(1000);
             }
         }
 
+        frame = frame.wrapping_add(1);
         delay.delay_ms(100);
     }
 }
@@ -0,0 +1,194 @@
+/*
+ * 	match flow around the rounds of the engine
+ *
+ * 	lobby -> countdown -> round -> round result -> countdown
+ * 	-> ... -> match result -> lobby. every round is played on
+ * 	a fresh GameState, the round winner gets a point and the
+ * 	first player with max_score points wins the match.
+ */
+
+use crate::engine::{ Checksum, Events, GameState, Outcome, MAX_PLAYERS };
+use crate::input::ControllerInput;
+use crate::rules::GameRules;
+
+// durations in frames, the game runs at 10 frames per second
+pub const COUNTDOWN_FRAMES: u8 = 30;
+pub const ROUND_RESULT_FRAMES: u8 = 30;
+pub const MATCH_RESULT_FRAMES: u8 = 50;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Phase {
+    // waiting for every player to press the C button
+    Lobby,
+    // frames left before the round starts
+    Countdown(u8),
+    Round,
+    // outcome of the round and frames left to show it
+    RoundResult(Outcome, u8),
+    // winner of the match and frames left to show it
+    MatchResult(usize, u8),
+}
+
+/// what happened during a single update, for the caller to react to
+#[derive(Clone, Copy)]
+pub struct MatchEvents {
+    // events of the round, if a round frame was played
+    pub round: Option<Events>,
+    pub round_won: Option<usize>,
+    pub match_won: Option<usize>,
+    // the points of the previous match were cleared
+    pub new_match: bool,
+}
+
+impl MatchEvents {
+    fn empty() -> Self {
+        MatchEvents {
+            round: None,
+            round_won: None,
+            match_won: None,
+            new_match: false,
+        }
+    }
+}
+
+pub struct Match {
+    phase: Phase,
+    state: GameState,
+    rules: GameRules,
+    ready: [bool; MAX_PLAYERS],
+    wins: [u8; MAX_PLAYERS],
+    max_score: u8,
+    player_count: usize,
+}
+
+impl Match {
+    pub fn new(rules: GameRules, max_score: u8, player_count: usize) -> Self {
+        let state = GameState::new(rules, player_count);
+        Match {
+            phase: Phase::Lobby,
+            player_count: state.player_count(),
+            state,
+            rules,
+            ready: [false; MAX_PLAYERS],
+            wins: [0; MAX_PLAYERS],
+            max_score,
+        }
+    }
+
+    pub fn player_count(&self) -> usize {
+        self.player_count
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // the current round, or the last one during the results
+    pub fn state(&self) -> &GameState {
+        &self.state
+    }
+
+    // players that have pressed C in the lobby
+    pub fn ready(&self) -> &[bool] {
+        &self.ready[..self.player_count]
+    }
+
+    // rounds won by each player in the current match
+    pub fn wins(&self) -> &[u8] {
+        &self.wins[..self.player_count]
+    }
+
+    pub fn max_score(&self) -> u8 {
+        self.max_score
+    }
+
+    /// hash of the phase, the points and the round, equal matches have equal checksums
+    pub fn checksum(&self) -> u32 {
+        let mut hash = Checksum::new();
+        let phase = match self.phase {
+            Phase::Lobby => [0, 0, 0],
+            Phase::Countdown(frames) => [1, frames, 0],
+            Phase::Round => [2, 0, 0],
+            Phase::RoundResult(Outcome::Winner(player), frames) => [3, frames, player as u8],
+            Phase::RoundResult(Outcome::Tie, frames) => [4, frames, 0],
+            Phase::MatchResult(winner, frames) => [5, frames, winner as u8],
+        };
+        hash.add(&phase);
+        for i in 0..self.player_count {
+            hash.add(&[self.ready[i] as u8, self.wins[i]]);
+        }
+        hash.add(&self.state.checksum().to_le_bytes());
+        hash.value()
+    }
+
+    /// advances the match by one frame using the given controller inputs
+    pub fn update(&mut self, inputs: [ControllerInput; MAX_PLAYERS]) -> MatchEvents {
+        let mut events = MatchEvents::empty();
+
+        self.phase = match self.phase {
+            Phase::Lobby => {
+                for (ready, input) in self.ready.iter_mut().zip(inputs.iter()).take(self.player_count) {
+                    if input.btn_c == 1 {
+                        *ready = true;
+                    }
+                }
+
+                if self.ready().iter().all(|r| *r) {
+                    self.wins = [0; MAX_PLAYERS];
+                    self.state = GameState::new(self.rules, self.player_count);
+                    events.new_match = true;
+                    Phase::Countdown(COUNTDOWN_FRAMES)
+                } else {
+                    Phase::Lobby
+                }
+            }
+            Phase::Countdown(frames) => {
+                if frames <= 1 {
+                    Phase::Round
+                } else {
+                    Phase::Countdown(frames - 1)
+                }
+            }
+            Phase::Round => {
+                let round = self.state.step(inputs);
+                events.round = Some(round);
+
+                match round.outcome {
+                    Some(Outcome::Winner(player)) => {
+                        self.wins[player] += 1;
+                        events.round_won = Some(player);
+
+                        if self.wins[player] >= self.max_score {
+                            events.match_won = Some(player);
+                            Phase::MatchResult(player, MATCH_RESULT_FRAMES)
+                        } else {
+                            Phase::RoundResult(Outcome::Winner(player), ROUND_RESULT_FRAMES)
+                        }
+                    }
+                    // Nobody gets a point from a tie, the round is played again
+                    Some(Outcome::Tie) => Phase::RoundResult(Outcome::Tie, ROUND_RESULT_FRAMES),
+                    None => Phase::Round,
+                }
+            }
+            Phase::RoundResult(outcome, frames) => {
+                if frames <= 1 {
+                    self.state = GameState::new(self.rules, self.player_count);
+                    Phase::Countdown(COUNTDOWN_FRAMES)
+                } else {
+                    Phase::RoundResult(outcome, frames - 1)
+                }
+            }
+            Phase::MatchResult(winner, frames) => {
+                if frames <= 1 {
+                    self.ready = [false; MAX_PLAYERS];
+                    self.state = GameState::new(self.rules, self.player_count);
+                    Phase::Lobby
+                } else {
+                    Phase::MatchResult(winner, frames - 1)
+                }
+            }
+        };
+
+        events
+    }
+}
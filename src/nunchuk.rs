@@ -24,34 +24,7 @@ use gd32vf103xx_hal::prelude::*;
 use gd32vf103xx_hal::delay::McycleDelay;
 use embedded_hal::blocking::delay::DelayMs;
 
-/// contains the controller data neatly formatted
-#[derive(Copy, Clone)]
-pub struct ControllerInput
-{
-	pub joy_x: i8,
-	pub joy_y: i8,
-	pub accel_x: i16,
-	pub accel_y: i16,
-	pub accel_z: i16,
-	pub btn_z: u8,
-	pub btn_c: u8,
-}
-
-impl ControllerInput
-{
-	pub fn empty() -> Self
-	{
-		ControllerInput {
-			joy_x: 0,
-			joy_y: 0,
-			accel_x: 0,
-			accel_y: 0,
-			accel_z: 0,
-			btn_z: 0,
-			btn_c: 0,
-		}
-	}
-}
+pub use crate::input::ControllerInput;
 
 pub struct Nunchuk<'a>
 {
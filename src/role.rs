@@ -0,0 +1,181 @@
+/*
+ * 	master, client or spectator, decided while booting
+ *
+ * 	the same firmware runs on every board. a strap pin tied
+ * 	to ground decides the role: PB12 for the master, PB13
+ * 	for a client and PB14 for a spectator. without a strap
+ * 	the role saved to the last flash page is used, a client
+ * 	if nothing has been saved. holding C and Z while booting
+ * 	switches the saved role to the next one.
+ *
+ * 	the saved role also keeps the number of players of a
+ * 	master and the id of a client or a spectator. the
+ * 	switch goes through them too: a master of 2, 3 and 4
+ * 	players, the clients 1 to 3 and the spectators 4 to 6.
+ * 	a strap keeps the number saved for its role.
+ *
+ * 	the wifi adapter is told the role with a command, it
+ * 	starts the access point for the master and connects to
+ * 	it for a client or a spectator.
+ */
+
+use core::mem::discriminant;
+use gd32vf103xx_hal::pac::FMC;
+use crate::adapter::Command;
+use crate::engine::MAX_PLAYERS;
+
+// marks a saved role in the upper half of the settings word
+const MAGIC: u32 = 0xB3A5;
+// every spectator needs its own id, 7 is the source of the adapter frames
+const FIRST_SPECTATOR: u8 = MAX_PLAYERS as u8;
+const LAST_SPECTATOR: u8 = 6;
+// FMC unlock sequence
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+extern "C" {
+    // the flash page reserved for the settings in memory-*.x
+    static _settings_start: u32;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Role {
+    // with the number of players in the match
+    Master(u8),
+    // with the player id the inputs are sent with
+    Client(u8),
+    // only shows the screen of the master, does not play. with the id its frames are sent with
+    Spectator(u8),
+}
+
+impl Role {
+    /// the command the wifi adapter picks its mode from
+    pub fn announcement(self) -> Command<'static> {
+        // The adapter connects to the master the same way for clients and spectators
+        Command::Role(matches!(self, Role::Master(_)))
+    }
+
+    /// the id this board sends its frames with, the master is always player 0
+    pub fn id(self) -> u8 {
+        match self {
+            Role::Master(_) => 0,
+            Role::Client(id) | Role::Spectator(id) => id,
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            Role::Master(players) if (players as usize) < MAX_PLAYERS => Role::Master(players + 1),
+            Role::Master(_) => Role::Client(1),
+            Role::Client(id) if (id as usize) < MAX_PLAYERS - 1 => Role::Client(id + 1),
+            Role::Client(_) => Role::Spectator(FIRST_SPECTATOR),
+            Role::Spectator(id) if id < LAST_SPECTATOR => Role::Spectator(id + 1),
+            Role::Spectator(_) => Role::Master(2),
+        }
+    }
+
+    // the kind in the low byte, the number in the high byte
+    fn code(self) -> u32 {
+        match self {
+            Role::Master(players) => 1 | (players as u32) << 8,
+            Role::Client(id) => 2 | (id as u32) << 8,
+            Role::Spectator(id) => 3 | (id as u32) << 8,
+        }
+    }
+
+    // a number that does not fit the kind, or none saved by an older
+    // firmware, is replaced by the first one
+    fn from_code(code: u32) -> Option<Self> {
+        let number = (code >> 8) as u8;
+        match code & 0xFF {
+            1 if (2..=MAX_PLAYERS as u8).contains(&number) => Some(Role::Master(number)),
+            1 => Some(Role::Master(2)),
+            2 if (1..MAX_PLAYERS as u8).contains(&number) => Some(Role::Client(number)),
+            2 => Some(Role::Client(1)),
+            3 if (FIRST_SPECTATOR..=LAST_SPECTATOR).contains(&number) => Some(Role::Spectator(number)),
+            3 => Some(Role::Spectator(FIRST_SPECTATOR)),
+            _ => None,
+        }
+    }
+}
+
+/// the role of this boot from the straps, the saved role and the held buttons.
+/// the straps are true when the pin is tied to ground
+pub fn select(fmc: &FMC, master_strap: bool, client_strap: bool, spectator_strap: bool, switch: bool) -> Role
+{
+    let saved = load().unwrap_or(Role::Client(1));
+    let strapped = if master_strap == true {
+        Some(Role::Master(2))
+    } else if client_strap == true {
+        Some(Role::Client(1))
+    } else if spectator_strap == true {
+        Some(Role::Spectator(FIRST_SPECTATOR))
+    } else {
+        None
+    };
+    if let Some(strapped) = strapped {
+        // The strap only picks the kind, the number saved for it is kept
+        if discriminant(&strapped) == discriminant(&saved) {
+            return saved;
+        }
+        return strapped;
+    }
+
+    if switch == true {
+        save(fmc, saved.next());
+        return saved.next();
+    }
+    saved
+}
+
+pub fn load() -> Option<Role>
+{
+    let word = unsafe { core::ptr::read_volatile(settings_address()) };
+    if word >> 16 != MAGIC {
+        return None;
+    }
+    Role::from_code(word & 0xFFFF)
+}
+
+/// erases the settings page and writes the role to it
+pub fn save(fmc: &FMC, role: Role)
+{
+    let address = settings_address() as u32;
+    let word = MAGIC << 16 | role.code();
+
+    if fmc.ctl0.read().lk().bit_is_set() {
+        fmc.key0.write(|w| unsafe { w.key().bits(KEY1) });
+        fmc.key0.write(|w| unsafe { w.key().bits(KEY2) });
+    }
+
+    // Erase the page
+    wait(fmc);
+    fmc.ctl0.modify(|_, w| w.per().set_bit());
+    fmc.addr0.write(|w| unsafe { w.addr().bits(address) });
+    fmc.ctl0.modify(|_, w| w.start().set_bit());
+    wait(fmc);
+    fmc.ctl0.modify(|_, w| w.per().clear_bit());
+
+    // The flash is programmed a half word at a time, lower half first
+    fmc.ctl0.modify(|_, w| w.pg().set_bit());
+    unsafe {
+        core::ptr::write_volatile(address as *mut u16, word as u16);
+        wait(fmc);
+        core::ptr::write_volatile((address + 2) as *mut u16, (word >> 16) as u16);
+        wait(fmc);
+    }
+    fmc.ctl0.modify(|_, w| w.pg().clear_bit());
+    fmc.ctl0.modify(|_, w| w.lk().set_bit());
+}
+
+fn settings_address() -> *const u32
+{
+    unsafe { &_settings_start as *const u32 }
+}
+
+fn wait(fmc: &FMC)
+{
+    while fmc.stat0.read().busy().bit_is_set() {}
+    // Clear the end of operation flag
+    fmc.stat0.write(|w| w.endf().set_bit());
+}
@@ -0,0 +1,122 @@
+/*
+ * 	gameplay tuning values of a match
+ *
+ * 	the presets are selected at boot, so balance can be
+ * 	tried out without editing the code and reflashing.
+ */
+
+use crate::game;
+
+// bytes of the rules sent over the link
+pub const ENCODED_LEN: usize = 22;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GameRules {
+    // frames to wait after shooting before moving or shooting again
+    pub shoot_timeout: u8,
+    // frames to wait after moving one cell
+    pub move_timeout: u8,
+    // joystick deflection (range -128..127) needed for moving
+    pub joystick_threshold: i8,
+    // cells painted around a stopped projectile in each direction
+    pub explosion_radius: usize,
+    // frames a projectile may fly before it explodes anyway
+    pub projectile_lifetime: usize,
+    // starting speed of a projectile in cells per frame
+    pub shot_speed: f32,
+    // distance of the aim indicator from the player in cells
+    pub aim_distance: f32,
+    // speed lost per frame as (numerator, denominator), negative
+    pub deceleration: (i32, i32),
+}
+
+impl GameRules {
+    /// the original balance of the game
+    pub const fn classic() -> Self {
+        GameRules {
+            shoot_timeout: 10,
+            move_timeout: 5,
+            joystick_threshold: 100,
+            explosion_radius: 2,
+            projectile_lifetime: 100,
+            shot_speed: 2.0,
+            aim_distance: 3.0,
+            deceleration: game::DECELERATION,
+        }
+    }
+
+    /// shorter waits and faster projectiles that stop sooner
+    pub const fn fast() -> Self {
+        GameRules {
+            shoot_timeout: 5,
+            move_timeout: 2,
+            joystick_threshold: 80,
+            explosion_radius: 2,
+            projectile_lifetime: 60,
+            shot_speed: 3.0,
+            aim_distance: 3.0,
+            deceleration: (-15, 100),
+        }
+    }
+
+    /// rapid fire, big explosions and projectiles that keep bouncing
+    pub const fn chaotic() -> Self {
+        GameRules {
+            shoot_timeout: 2,
+            move_timeout: 1,
+            joystick_threshold: 60,
+            explosion_radius: 3,
+            projectile_lifetime: 150,
+            shot_speed: 4.0,
+            aim_distance: 4.0,
+            deceleration: (-4, 100),
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "classic" => Some(GameRules::classic()),
+            "fast" => Some(GameRules::fast()),
+            "chaotic" => Some(GameRules::chaotic()),
+            _ => None,
+        }
+    }
+
+    /// the rules as bytes, so the devices of a lockstep match play by the same ones
+    pub fn encode(&self) -> [u8; ENCODED_LEN] {
+        let mut out = [0u8; ENCODED_LEN];
+        out[0] = self.shoot_timeout;
+        out[1] = self.move_timeout;
+        out[2] = self.joystick_threshold as u8;
+        out[3] = self.explosion_radius as u8;
+        out[4..6].copy_from_slice(&(self.projectile_lifetime as u16).to_le_bytes());
+        out[6..10].copy_from_slice(&self.shot_speed.to_bits().to_le_bytes());
+        out[10..14].copy_from_slice(&self.aim_distance.to_bits().to_le_bytes());
+        out[14..18].copy_from_slice(&self.deceleration.0.to_le_bytes());
+        out[18..22].copy_from_slice(&self.deceleration.1.to_le_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ENCODED_LEN {
+            return None;
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(GameRules {
+            shoot_timeout: bytes[0],
+            move_timeout: bytes[1],
+            joystick_threshold: bytes[2] as i8,
+            explosion_radius: bytes[3] as usize,
+            projectile_lifetime: u16::from_le_bytes([bytes[4], bytes[5]]) as usize,
+            shot_speed: f32::from_bits(u32_at(6)),
+            aim_distance: f32::from_bits(u32_at(10)),
+            deceleration: (u32_at(14) as i32, u32_at(18) as i32),
+        })
+    }
+}
+
+impl Default for GameRules {
+    fn default() -> Self {
+        GameRules::classic()
+    }
+}
@@ -71,6 +71,13 @@ where T: OutputPin
         self.update_scoreboard();
     }
 
+    // sets the score, e.g. to the one received from the master
+    pub fn set_score(&mut self, score: u8)
+    {
+        self.score = if score > self.max_score { self.max_score } else { score };
+        self.update_scoreboard();
+    }
+
     // updates the scoreboard leds
     pub fn update_scoreboard(&mut self)
     {
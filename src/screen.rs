@@ -0,0 +1,214 @@
+/*
+ * 	what the led matrix shows, independent of the leds
+ *
+ * 	every cell inside the borders has a shade instead of a
+ * 	color, so a screen is small enough to be sent over the
+ * 	link to the clients. the master builds it from the match,
+ * 	the firmware and the simulator map the shades to colors.
+ */
+
+use crate::game::BOARD_WIDTH;
+use crate::engine::{ GameState, Outcome, MAX_PLAYERS };
+use crate::matchstate::{ self, Match, Phase };
+
+// last row and column inside the borders
+const LAST: usize = BOARD_WIDTH - 2;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shade {
+    Empty,
+    // territory or a result screen in the color of the player
+    Owner(u8),
+    Projectile,
+    Target,
+    Player,
+    // the countdown and a tied round
+    Highlight,
+}
+
+impl Shade {
+    // 4 bits, two cells fit in a byte
+    pub fn to_u8(self) -> u8 {
+        match self {
+            Shade::Empty => 0,
+            Shade::Owner(owner) => 1 + (owner & 0x03),
+            Shade::Projectile => 5,
+            Shade::Target => 6,
+            Shade::Player => 7,
+            Shade::Highlight => 8,
+        }
+    }
+
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0 => Some(Shade::Empty),
+            1..=4 => Some(Shade::Owner(value - 1)),
+            5 => Some(Shade::Projectile),
+            6 => Some(Shade::Target),
+            7 => Some(Shade::Player),
+            8 => Some(Shade::Highlight),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Screen {
+    // indexed [x][y] like the led matrix, the border cells stay empty
+    cells: [[Shade; BOARD_WIDTH]; BOARD_WIDTH],
+    // painted cells of each player in the current round
+    pub score: [u8; MAX_PLAYERS],
+    // rounds won in the current match
+    pub wins: [u8; MAX_PLAYERS],
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Screen {
+            cells: [[Shade::Empty; BOARD_WIDTH]; BOARD_WIDTH],
+            score: [0; MAX_PLAYERS],
+            wins: [0; MAX_PLAYERS],
+        }
+    }
+
+    /// the lobby, countdown and result screens around the rounds
+    pub fn of_match(game_match: &Match) -> Self {
+        let mut screen = Screen::new();
+        let state = game_match.state();
+        screen.score = state.score();
+        screen.wins[..game_match.player_count()].copy_from_slice(game_match.wins());
+
+        match game_match.phase() {
+            Phase::Lobby => {
+                // Each player has a band of rows that lights up in their color when they are ready
+                let bands = game_match.player_count();
+                for (i, ready) in game_match.ready().iter().enumerate() {
+                    let shade = if *ready { Shade::Owner(i as u8) } else { Shade::Empty };
+                    screen.fill_rows(1 + i * LAST / bands, (i + 1) * LAST / bands, shade);
+                }
+                screen.draw_players(state);
+            }
+            Phase::Countdown(frames) => {
+                // A bar shrinking towards the top until the round starts
+                let rows = (frames as usize * LAST) / matchstate::COUNTDOWN_FRAMES as usize;
+                if rows > 0 {
+                    screen.fill_rows(1, rows, Shade::Highlight);
+                }
+                screen.draw_players(state);
+            }
+            Phase::Round => screen.draw_round(state),
+            Phase::RoundResult(outcome, frames) => {
+                // Blink the round winner color over the final board
+                if (frames / 5) % 2 == 0 {
+                    let shade = match outcome {
+                        Outcome::Winner(player) => Shade::Owner(player as u8),
+                        Outcome::Tie => Shade::Highlight,
+                    };
+                    screen.fill_rows(1, LAST, shade);
+                } else {
+                    screen.draw_round(state);
+                }
+            }
+            Phase::MatchResult(winner, frames) => {
+                // Blink the match winner color
+                if (frames / 2) % 2 == 0 {
+                    screen.fill_rows(1, LAST, Shade::Owner(winner as u8));
+                }
+            }
+        }
+        screen
+    }
+
+    /// the match is paused until the player is heard from again,
+    /// a checkerboard in the color of the player that flips every half second
+    pub fn waiting(player: usize, frames: u32) -> Self {
+        let mut screen = Screen::new();
+        let phase = ((frames / 5) % 2) as usize;
+        for y in 1..=LAST {
+            for x in 1..=LAST {
+                if (x + y) % 2 == phase {
+                    screen.set(x, y, Shade::Owner(player as u8));
+                }
+            }
+        }
+        screen
+    }
+
+    /// the master refused the player, a cross over the color of the player
+    pub fn refused(player: usize) -> Self {
+        let mut screen = Screen::new();
+        for y in 1..=LAST {
+            for x in 1..=LAST {
+                let shade = if x == y || x + y == LAST + 1 { Shade::Target } else { Shade::Owner(player as u8) };
+                screen.set(x, y, shade);
+            }
+        }
+        screen
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Shade {
+        if x >= BOARD_WIDTH || y >= BOARD_WIDTH {
+            return Shade::Empty;
+        }
+        self.cells[x][y]
+    }
+
+    // cells outside the borders are ignored
+    pub fn set(&mut self, x: usize, y: usize, shade: Shade) {
+        if x >= 1 && y >= 1 && x <= LAST && y <= LAST {
+            self.cells[x][y] = shade;
+        }
+    }
+
+    /// the cells inside the borders row by row as (x, y, shade)
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize, Shade)> + '_ {
+        (1..=LAST).flat_map(move |y| (1..=LAST).map(move |x| (x, y, self.cells[x][y])))
+    }
+}
+
+// private methods
+impl Screen {
+    fn fill_rows(&mut self, first: usize, last: usize, shade: Shade) {
+        for y in first..=last {
+            for x in 1..=LAST {
+                self.set(x, y, shade);
+            }
+        }
+    }
+
+    fn draw_players(&mut self, state: &GameState) {
+        for player in state.players().iter() {
+            self.set(player.x as usize, player.y as usize, Shade::Player);
+        }
+    }
+
+    // the territory, the projectiles in flight and the players with their aim
+    fn draw_round(&mut self, state: &GameState) {
+        for y in 1..=LAST {
+            for x in 1..=LAST {
+                let shade = match state.owner(x, y) {
+                    Some(owner) => Shade::Owner(owner as u8),
+                    None => Shade::Empty,
+                };
+                self.set(x, y, shade);
+            }
+        }
+
+        for (x, y) in state.projectiles() {
+            self.set(x, y, Shade::Projectile);
+        }
+
+        for player in state.players().iter() {
+            if let Some((x, y)) = player.target() {
+                self.set(x, y, Shade::Target);
+            }
+        }
+        self.draw_players(state);
+    }
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Screen::new()
+    }
+}
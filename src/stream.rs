@@ -0,0 +1,186 @@
+/*
+ * 	streams the screen of the master to the clients
+ *
+ * 	every frame the master sends the cells that changed
+ * 	since the previous frame together with the scores. a
+ * 	full copy of the screen is sent every RESYNC_INTERVAL
+ * 	frames, when a client asks for it and when too much
+ * 	changed at once. a client that notices a gap in the
+ * 	frames ignores the changes until the next full copy.
+ *
+ * 	delta:  score[4] wins[4] count (x << 4 | y, shade)[count]
+ * 	resync: score[4] wins[4] shades of the inside cells, row
+ * 	        by row, two in a byte with the first one high
+ */
+
+use crate::game::BOARD_WIDTH;
+use crate::game::link::{ Frame, FrameKind, MAX_PAYLOAD };
+use crate::engine::MAX_PLAYERS;
+use crate::screen::{ Screen, Shade };
+
+// frames between the full copies, 5 seconds
+pub const RESYNC_INTERVAL: u16 = 50;
+
+const HEADER_LEN: usize = 2 * MAX_PLAYERS;
+// changed cells that fit in one delta frame
+const MAX_CHANGES: usize = (MAX_PAYLOAD - HEADER_LEN - 1) / 2;
+
+pub struct ScreenSender {
+    last: Screen,
+    since_resync: u16,
+    resync_requested: bool,
+}
+
+impl ScreenSender {
+    pub fn new() -> Self {
+        ScreenSender {
+            last: Screen::new(),
+            since_resync: 0,
+            // the clients start from an empty screen, so begin with a full copy
+            resync_requested: true,
+        }
+    }
+
+    /// a client joined or lost frames
+    pub fn request_resync(&mut self) {
+        self.resync_requested = true;
+    }
+
+    /// the frame to send for this screen, writes the payload and returns its kind and length
+    pub fn next(&mut self, screen: &Screen, payload: &mut [u8; MAX_PAYLOAD]) -> (FrameKind, usize) {
+        write_header(screen, payload);
+
+        // Changes against the screen the clients have now
+        let mut changes = 0;
+        let mut len = HEADER_LEN + 1;
+        for (x, y, shade) in screen.cells() {
+            if self.last.get(x, y) == shade {
+                continue;
+            }
+            changes += 1;
+            if changes <= MAX_CHANGES {
+                payload[len] = (x << 4 | y) as u8;
+                payload[len + 1] = shade.to_u8();
+                len += 2;
+            }
+        }
+        self.last = *screen;
+
+        if self.resync_requested || self.since_resync >= RESYNC_INTERVAL || changes > MAX_CHANGES {
+            self.resync_requested = false;
+            self.since_resync = 0;
+
+            let mut len = HEADER_LEN;
+            for (i, (_, _, shade)) in screen.cells().enumerate() {
+                if i % 2 == 0 {
+                    payload[len] = shade.to_u8() << 4;
+                } else {
+                    payload[len] |= shade.to_u8();
+                    len += 1;
+                }
+            }
+            return (FrameKind::ScreenResync, len);
+        }
+
+        self.since_resync += 1;
+        payload[HEADER_LEN] = changes as u8;
+        (FrameKind::ScreenDelta, len)
+    }
+}
+
+impl Default for ScreenSender {
+    fn default() -> Self {
+        ScreenSender::new()
+    }
+}
+
+pub struct ScreenReceiver {
+    screen: Screen,
+    synced: bool,
+}
+
+impl ScreenReceiver {
+    pub fn new() -> Self {
+        ScreenReceiver {
+            screen: Screen::new(),
+            synced: false,
+        }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// false until the first full copy and after a gap in the frames
+    pub fn synced(&self) -> bool {
+        self.synced
+    }
+
+    /// frames from the master were lost, the changes can not be trusted
+    pub fn lost_sync(&mut self) {
+        self.synced = false;
+    }
+
+    /// applies a screen frame, returns true if the screen changed
+    pub fn apply(&mut self, frame: &Frame) -> bool {
+        let data = frame.payload();
+        if data.len() < HEADER_LEN {
+            return false;
+        }
+
+        match frame.kind {
+            FrameKind::ScreenResync => {
+                let mut screen = Screen::new();
+                read_header(&mut screen, data);
+                let cells = &data[HEADER_LEN..];
+                let mut i = 0;
+                for y in 1..(BOARD_WIDTH - 1) {
+                    for x in 1..(BOARD_WIDTH - 1) {
+                        let byte = match cells.get(i / 2) {
+                            Some(b) => *b,
+                            None => return false,
+                        };
+                        let value = if i % 2 == 0 { byte >> 4 } else { byte & 0x0F };
+                        screen.set(x, y, Shade::from_u8(value).unwrap_or(Shade::Empty));
+                        i += 1;
+                    }
+                }
+                self.screen = screen;
+                self.synced = true;
+                true
+            }
+            FrameKind::ScreenDelta => {
+                if !self.synced {
+                    return false;
+                }
+                read_header(&mut self.screen, data);
+                let count = data.get(HEADER_LEN).copied().unwrap_or(0) as usize;
+                for change in data[HEADER_LEN + 1..].chunks(2).take(count) {
+                    if change.len() < 2 {
+                        break;
+                    }
+                    let (x, y) = ((change[0] >> 4) as usize, (change[0] & 0x0F) as usize);
+                    self.screen.set(x, y, Shade::from_u8(change[1]).unwrap_or(Shade::Empty));
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for ScreenReceiver {
+    fn default() -> Self {
+        ScreenReceiver::new()
+    }
+}
+
+fn write_header(screen: &Screen, payload: &mut [u8; MAX_PAYLOAD]) {
+    payload[..MAX_PLAYERS].copy_from_slice(&screen.score);
+    payload[MAX_PLAYERS..HEADER_LEN].copy_from_slice(&screen.wins);
+}
+
+fn read_header(screen: &mut Screen, data: &[u8]) {
+    screen.score.copy_from_slice(&data[..MAX_PLAYERS]);
+    screen.wins.copy_from_slice(&data[MAX_PLAYERS..HEADER_LEN]);
+}
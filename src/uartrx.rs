@@ -0,0 +1,163 @@
+/*
+ * 	interrupt driven receive of USART0, the wifi link
+ *
+ * 	the USART0 interrupt moves every received byte to a
+ * 	ring buffer, the game loop drains it once per frame
+ * 	without waiting. receive errors are counted instead of
+ * 	being returned to the game loop.
+ */
+
+use core::cell::RefCell;
+use gd32vf103xx_hal::eclic::{ EclicExt, Level, LevelPriorityBits, Priority, TriggerType };
+use gd32vf103xx_hal::pac::{ Interrupt, ECLIC, USART0 };
+use gd32vf103xx_hal::prelude::*;
+use gd32vf103xx_hal::serial::{ Error, Rx };
+use riscv::interrupt;
+use riscv::interrupt::Mutex;
+
+// 74880 baud is about 750 bytes per 100 ms frame
+const BUFFER_SIZE: usize = 1024;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct RxErrors {
+    pub overrun: u32,
+    pub framing: u32,
+    pub noise: u32,
+    pub parity: u32,
+    // bytes lost because the game loop did not drain the buffer in time
+    pub dropped: u32,
+}
+
+pub struct RingBuffer<const N: usize> {
+    data: [u8; N],
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        RingBuffer { data: [0; N], start: 0, len: 0 }
+    }
+
+    // returns false if the buffer is full
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len == N {
+            return false;
+        }
+        self.data[(self.start + self.len) % N] = byte;
+        self.len += 1;
+        true
+    }
+
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.data[self.start];
+        self.start = (self.start + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+struct Receiver {
+    rx: Rx<USART0>,
+    buffer: RingBuffer<BUFFER_SIZE>,
+    errors: RxErrors,
+}
+
+static RECEIVER: Mutex<RefCell<Option<Receiver>>> = Mutex::new(RefCell::new(None));
+
+/// moves the receiver to the interrupt and enables the receive interrupt
+pub fn listen(mut rx: Rx<USART0>)
+{
+    rx.listen();
+    interrupt::free(|cs| {
+        *RECEIVER.borrow(cs).borrow_mut() = Some(Receiver {
+            rx,
+            buffer: RingBuffer::new(),
+            errors: RxErrors::default(),
+        });
+    });
+
+    ECLIC::reset();
+    ECLIC::set_threshold_level(Level::L0);
+    ECLIC::set_level_priority_bits(LevelPriorityBits::L3P1);
+    ECLIC::setup(Interrupt::USART0, TriggerType::Level, Level::L1, Priority::P1);
+    unsafe {
+        ECLIC::unmask(Interrupt::USART0);
+        riscv::interrupt::enable();
+    }
+}
+
+/// copies the received bytes to out without waiting, returns how many there were
+pub fn read(out: &mut [u8]) -> usize
+{
+    interrupt::free(|cs| {
+        let mut receiver = RECEIVER.borrow(cs).borrow_mut();
+        let receiver = match receiver.as_mut() {
+            Some(r) => r,
+            None => return 0,
+        };
+
+        let mut count = 0;
+        while count < out.len() {
+            match receiver.buffer.pop() {
+                Some(byte) => out[count] = byte,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    })
+}
+
+pub fn errors() -> RxErrors
+{
+    interrupt::free(|cs| {
+        match RECEIVER.borrow(cs).borrow().as_ref() {
+            Some(receiver) => receiver.errors,
+            None => RxErrors::default(),
+        }
+    })
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+fn USART0()
+{
+    interrupt::free(|cs| {
+        let mut receiver = RECEIVER.borrow(cs).borrow_mut();
+        let receiver = match receiver.as_mut() {
+            Some(r) => r,
+            None => return,
+        };
+
+        // Empty the data register, reading it also clears the error flags
+        loop {
+            match receiver.rx.read() {
+                Ok(byte) => {
+                    if receiver.buffer.push(byte) == false {
+                        receiver.errors.dropped += 1;
+                    }
+                }
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(error)) => match error {
+                    Error::Overrun => receiver.errors.overrun += 1,
+                    Error::Framing => receiver.errors.framing += 1,
+                    Error::Noise => receiver.errors.noise += 1,
+                    Error::Parity => receiver.errors.parity += 1,
+                    _ => {}
+                },
+            }
+        }
+    });
+}
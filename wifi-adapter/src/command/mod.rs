@@ -0,0 +1,225 @@
+#![allow(dead_code)]
+
+mod reader;
+
+use crate::control::{ Event, Status };
+use crate::events;
+use reader::Reader;
+
+// The longest text argument, a WPA2 password
+const ARG_MAX: usize = 63;
+
+pub const DEFAULT_SSID: &str = "BMR_wirele";
+pub const DEFAULT_PASSWORD: &str = "wire123456";
+pub const DEFAULT_CHANNEL: u8 = 7;
+pub const DEFAULT_PORT: u16 = 8000;
+
+// The settings take three sectors below the rf calibration one, see user_rf_cal_sector_set
+const SETTINGS_SECTOR: u16 = 512 - 8;
+const SETTINGS_MAGIC: u32 = 0x424d_5201;
+
+extern "C" {
+    pub fn system_param_save_with_protect(start_sec: u16, param: *mut u32, len: u16) -> u8;
+    pub fn system_param_load(start_sec: u16, offset: u16, param: *mut u32, len: u16) -> u8;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Command {
+    Ssid(Arg),
+    Password(Arg),
+    Channel(u8),
+    Port(u16),
+    // true for the master
+    Role(bool),
+    // the connection of the master the next bytes go to, None for all of them
+    Send(Option<u8>),
+    Status,
+    Reconnect,
+    Reboot,
+    Invalid,
+}
+
+// A text argument copied out of the line, the line is read over again for the next command
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Arg {
+    bytes: [u8; ARG_MAX],
+    len: u8,
+}
+
+impl Arg {
+    fn new(text: &[u8]) -> Self {
+        let mut arg = Arg { bytes: [0; ARG_MAX], len: text.len() as u8 };
+        arg.bytes[..text.len()].copy_from_slice(text);
+        arg
+    }
+
+    pub fn as_str(&self) -> &str {
+        text(&self.bytes[..self.len as usize])
+    }
+}
+
+// Kept in the flash, the sdk wants the length to be a multiple of four
+#[repr(C)]
+pub struct Settings {
+    magic: u32,
+    ssid: [u8; 32],
+    password: [u8; 64],
+    ssid_len: u8,
+    password_len: u8,
+    pub port: u16,
+    pub channel: u8,
+    padding: [u8; 3],
+}
+
+impl Settings {
+    pub fn ssid(&self) -> &str {
+        text(&self.ssid[..self.ssid_len as usize])
+    }
+
+    pub fn password(&self) -> &str {
+        text(&self.password[..self.password_len as usize])
+    }
+}
+
+pub static mut SETTINGS: Settings = Settings {
+    magic: 0,
+    ssid: [0; 32],
+    password: [0; 64],
+    ssid_len: 0,
+    password_len: 0,
+    port: 0,
+    channel: 0,
+    padding: [0; 3],
+};
+
+// The bytes of a command read so far
+static mut READER: Reader = Reader::new();
+
+/// Reads the settings saved by the commands, or takes the defaults
+pub fn load() {
+    unsafe {
+        let param = &mut SETTINGS as *mut Settings as *mut u32;
+        let size = core::mem::size_of::<Settings>() as u16;
+        if system_param_load(SETTINGS_SECTOR, 0, param, size) == 0 || SETTINGS.magic != SETTINGS_MAGIC {
+            SETTINGS.magic = SETTINGS_MAGIC;
+            store_text(&mut SETTINGS.ssid, &mut SETTINGS.ssid_len, DEFAULT_SSID);
+            store_text(&mut SETTINGS.password, &mut SETTINGS.password_len, DEFAULT_PASSWORD);
+            SETTINGS.channel = DEFAULT_CHANNEL;
+            SETTINGS.port = DEFAULT_PORT;
+        }
+    };
+}
+
+/// Changes a setting and saves it, it is used from the next reconnect on
+pub fn set(command: &Command) -> bool {
+    unsafe {
+        match *command {
+            Command::Ssid(ssid) => store_text(&mut SETTINGS.ssid, &mut SETTINGS.ssid_len, ssid.as_str()),
+            Command::Password(password) => store_text(&mut SETTINGS.password, &mut SETTINGS.password_len, password.as_str()),
+            Command::Channel(channel) => SETTINGS.channel = channel,
+            Command::Port(port) => SETTINGS.port = port,
+            _ => return false,
+        }
+        let param = &mut SETTINGS as *mut Settings as *mut u32;
+        return system_param_save_with_protect(SETTINGS_SECTOR, param, core::mem::size_of::<Settings>() as u16) != 0;
+    };
+}
+
+/// Takes a byte read from the uart, the bytes that are not part of a command are handed to forward.
+/// Returns the command once its line has been read.
+pub fn feed(byte: u8, forward: fn(u8)) -> Option<Command> {
+    unsafe { READER.feed(byte, forward).map(parse) }
+}
+
+/// Hands the bytes of a prefix that stopped arriving to forward, called once the uart has
+/// been read. A prefix cut by the end of an update waits one update for the rest of it.
+pub fn flush(forward: fn(u8)) {
+    unsafe { READER.flush(forward) };
+}
+
+/// Reads a command line without the prefix, also the role line the boards send at boot
+pub fn parse(line: &[u8]) -> Command {
+    let mut line = line;
+    if line.last() == Some(&('\r' as u8)) {
+        line = &line[..line.len() - 1];
+    }
+    let (word, arg) = match line.iter().position(|b| *b == ' ' as u8) {
+        Some(space) => (&line[..space], &line[space + 1..]),
+        None => (line, &line[line.len()..]),
+    };
+
+    match word {
+        b"SSID" if printable(arg, 1, 31) => Command::Ssid(Arg::new(arg)),
+        // WPA2 takes 8 to 63 characters
+        b"PASS" if printable(arg, 8, ARG_MAX) => Command::Password(Arg::new(arg)),
+        b"CHAN" => match number(arg) {
+            Some(channel) if channel >= 1 && channel <= 13 => Command::Channel(channel as u8),
+            _ => Command::Invalid,
+        },
+        b"PORT" => match number(arg) {
+            Some(port) if port >= 1 && port <= 65535 => Command::Port(port as u16),
+            _ => Command::Invalid,
+        },
+        b"ROLE" if arg == b"MASTER" => Command::Role(true),
+        b"ROLE" if arg == b"CLIENT" => Command::Role(false),
+        b"SEND" if arg == b"ALL" => Command::Send(None),
+        b"SEND" => match number(arg) {
+            Some(id) if id < 256 => Command::Send(Some(id as u8)),
+            _ => Command::Invalid,
+        },
+        b"STATUS" if arg.is_empty() => Command::Status,
+        b"RECONNECT" if arg.is_empty() => Command::Reconnect,
+        b"REBOOT" if arg.is_empty() => Command::Reboot,
+        _ => Command::Invalid,
+    }
+}
+
+/// Answers a command with OK or ERR
+pub fn reply(ok: bool) {
+    events::send(Event::Reply(ok));
+}
+
+/// Answers STATUS, links is the number of connections to the other boards
+pub fn reply_status(role: u8, links: usize) {
+    unsafe {
+        events::send(Event::Status(Status {
+            role,
+            channel: SETTINGS.channel,
+            port: SETTINGS.port,
+            links: links as u8,
+            ssid: SETTINGS.ssid(),
+        }));
+    };
+}
+
+fn store_text(to: &mut [u8], len: &mut u8, text: &str) {
+    let bytes = text.as_bytes();
+    to[..bytes.len()].copy_from_slice(bytes);
+    // The sdk reads the strings until a NULL
+    for byte in to[bytes.len()..].iter_mut() {
+        *byte = 0;
+    }
+    *len = bytes.len() as u8;
+}
+
+fn printable(text: &[u8], min: usize, max: usize) -> bool {
+    text.len() >= min && text.len() <= max && text.iter().all(|b| *b >= ' ' as u8 && *b <= '~' as u8)
+}
+
+fn text(bytes: &[u8]) -> &str {
+    core::str::from_utf8(bytes).unwrap_or("")
+}
+
+fn number(text: &[u8]) -> Option<u32> {
+    if text.is_empty() || text.len() > 5 {
+        return None;
+    }
+    let mut value = 0;
+    for byte in text.iter() {
+        if *byte < '0' as u8 || *byte > '9' as u8 {
+            return None;
+        }
+        value = value * 10 + (*byte - '0' as u8) as u32;
+    }
+    Some(value)
+}
@@ -0,0 +1,149 @@
+// Finds the command lines in the bytes of the board. Nothing in here touches the sdk, the
+// simulator includes this file for the tests.
+
+// A command is a line starting with the prefix, the adapter takes it out of the bytes going to
+// the other boards and answers with a control frame. The frames of the game are binary, the
+// prefix is long enough not to turn up in them by chance.
+pub const PREFIX: &[u8] = b"\x1bBRM ";
+const LINE_MAX: usize = 80;
+
+/// The bytes of a command read so far
+pub struct Reader {
+    // The bytes of the prefix matched
+    matched: usize,
+    line: [u8; LINE_MAX],
+    len: usize,
+    // A part of the prefix was held through the end of an update without a byte after it
+    waited: bool,
+}
+
+impl Reader {
+    pub const fn new() -> Self {
+        Reader { matched: 0, line: [0; LINE_MAX], len: 0, waited: false }
+    }
+
+    /// Takes a byte read from the uart, the bytes that are not part of a command are handed to
+    /// forward. Returns the line of a command without the prefix once it has been read.
+    pub fn feed<F: FnMut(u8)>(&mut self, byte: u8, mut forward: F) -> Option<&[u8]> {
+        self.waited = false;
+        if self.matched == PREFIX.len() {
+            if byte == b'\n' {
+                self.matched = 0;
+                let len = self.len;
+                self.len = 0;
+                return Some(&self.line[..len]);
+            }
+            // A line too long is not a command, it is answered once it ends
+            if self.len < LINE_MAX {
+                self.line[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.line[0] = 0;
+            }
+            return None;
+        }
+
+        if byte == PREFIX[self.matched] {
+            self.matched += 1;
+            return None;
+        }
+
+        // Not a command after all, the bytes held back go on in the same order
+        for held in PREFIX[..self.matched].iter() {
+            forward(*held);
+        }
+        if byte == PREFIX[0] {
+            self.matched = 1;
+        } else {
+            self.matched = 0;
+            forward(byte);
+        }
+        None
+    }
+
+    /// Called once the uart has been read in an update. The start of a prefix cut by the update
+    /// is held until the next one, when no byte came after it for a whole update it was not a
+    /// command and its bytes are handed to forward.
+    pub fn flush<F: FnMut(u8)>(&mut self, mut forward: F) {
+        if self.matched == 0 || self.matched == PREFIX.len() {
+            return;
+        }
+        if self.waited {
+            for held in PREFIX[..self.matched].iter() {
+                forward(*held);
+            }
+            self.matched = 0;
+            self.waited = false;
+        } else {
+            self.waited = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bytes forwarded and the command lines read in one update
+    fn update(reader: &mut Reader, bytes: &[u8], forwarded: &mut Vec<u8>) -> Vec<Vec<u8>> {
+        let mut lines = Vec::new();
+        for byte in bytes.iter() {
+            if let Some(line) = reader.feed(*byte, |b| forwarded.push(b)) {
+                lines.push(line.to_vec());
+            }
+        }
+        reader.flush(|b| forwarded.push(b));
+        lines
+    }
+
+    #[test]
+    fn other_bytes_are_forwarded() {
+        let mut reader = Reader::new();
+        let mut forwarded = Vec::new();
+        let lines = update(&mut reader, &[0xAA, 1, 2, 0x1b, b'B', 3], &mut forwarded);
+        assert!(lines.is_empty());
+        assert_eq!(forwarded, vec![0xAA, 1, 2, 0x1b, b'B', 3]);
+    }
+
+    #[test]
+    fn a_command_is_taken_out() {
+        let mut reader = Reader::new();
+        let mut forwarded = Vec::new();
+        let lines = update(&mut reader, b"\xAA\x1bBRM STATUS\n\x01", &mut forwarded);
+        assert_eq!(lines, vec![b"STATUS".to_vec()]);
+        assert_eq!(forwarded, vec![0xAA, 1]);
+    }
+
+    #[test]
+    fn a_prefix_split_between_updates_is_a_command() {
+        let mut reader = Reader::new();
+        let mut forwarded = Vec::new();
+        assert!(update(&mut reader, b"\x01\x1bBR", &mut forwarded).is_empty());
+        let lines = update(&mut reader, b"M STATUS\n", &mut forwarded);
+        assert_eq!(lines, vec![b"STATUS".to_vec()]);
+        assert_eq!(forwarded, vec![1]);
+    }
+
+    #[test]
+    fn a_prefix_going_on_differently_is_forwarded() {
+        let mut reader = Reader::new();
+        let mut forwarded = Vec::new();
+        update(&mut reader, b"\x01\x1bBR", &mut forwarded);
+        assert_eq!(forwarded, vec![1]);
+        update(&mut reader, b"X\x02", &mut forwarded);
+        assert_eq!(forwarded, b"\x01\x1bBRX\x02".to_vec());
+    }
+
+    #[test]
+    fn a_prefix_stopped_for_a_whole_update_is_forwarded() {
+        let mut reader = Reader::new();
+        let mut forwarded = Vec::new();
+        update(&mut reader, b"\x01\x1b", &mut forwarded);
+        update(&mut reader, b"", &mut forwarded);
+        assert_eq!(forwarded, vec![1, 0x1b]);
+        // The next prefix is matched from its start
+        let lines = update(&mut reader, b"\x1bBRM REBOOT\n", &mut forwarded);
+        assert_eq!(lines, vec![b"REBOOT".to_vec()]);
+        assert_eq!(forwarded, vec![1, 0x1b]);
+    }
+}
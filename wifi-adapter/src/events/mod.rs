@@ -0,0 +1,30 @@
+#![allow(dead_code)]
+
+#[path = "../uart/mod.rs"] mod uart;
+
+use crate::control::Event;
+use crate::link::{ Frame, FrameKind, ADAPTER_SOURCE, MAX_FRAME, MAX_PAYLOAD };
+
+// Sequence number of the next frame, the board does not check them for the adapter
+static mut SEQ: u8 = 0;
+
+/// Tells the board what happened in a control frame, the only thing the adapter writes
+/// to the uart besides the bytes of the other boards
+pub fn send(event: Event) {
+    let mut payload = [0u8; MAX_PAYLOAD];
+    let len = event.encode(&mut payload);
+    let mut bytes = [0u8; MAX_FRAME];
+
+    unsafe {
+        let frame = match Frame::new(FrameKind::Adapter, ADAPTER_SOURCE, SEQ, &payload[..len]) {
+            Ok(frame) => frame,
+            Err(_) => return,
+        };
+        if let Ok(count) = frame.encode(&mut bytes) {
+            for byte in bytes[..count].iter() {
+                uart::writechr(*byte);
+            }
+            SEQ = SEQ.wrapping_add(1);
+        }
+    };
+}
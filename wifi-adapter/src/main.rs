@@ -4,9 +4,14 @@
 #[allow(unused_mut)]
 #[allow(dead_code)]
 
-const SERVER_MODE: u32 = 0;
-const SSID: &str = "BMR_wirele";
-const PASSWORD: &str = "wire123456";
+// The board tells the role with a line over the uart at boot
+const ROLE_UNKNOWN: u8 = 0;
+const ROLE_CLIENT: u8 = 1;
+const ROLE_SERVER: u8 = 2;
+// Tcp or udp between the adapters, the adapters of all the boards have to use the same
+const NET_MODE: net::Mode = net::Mode::Tcp;
+// The soft-AP of the master
+const MASTER_IP: [u8; 4] = [192, 168, 4, 1];
 
 
 pub type ETSTimerFunc = unsafe extern "C" fn(timer_arg: *const u32);
@@ -44,6 +49,7 @@ extern "C" {
     pub fn wifi_get_opmode() -> u8;
     pub fn wifi_get_phy_mode() -> u32;
     pub fn system_soft_wdt_feed();
+    pub fn system_restart();
 
     pub fn ets_timer_disarm(timer: *mut os_timer_t);
     pub fn ets_timer_arm_new(timer: *mut os_timer_t, time: u32, repeat: u8, ms: u8);
@@ -136,8 +142,16 @@ fn gpio_set(pin:u32, val:u32) -> bool {
 
 mod uart;
 mod wifi;
-mod server;
-mod client;
+mod net;
+mod command;
+mod events;
+// The frames of the boards, shared with the firmware, the adapter only encodes its own
+#[allow(dead_code)] #[path = "../../src/game/link.rs"] mod link;
+#[allow(dead_code)] #[path = "../../src/game/control.rs"] mod control;
+
+use command::{ Command, SETTINGS };
+use control::{ AdapterError, Event, WifiState };
+use net::forward::Forwarder;
 
 use core::panic::PanicInfo;
 
@@ -146,7 +160,7 @@ fn panic(_info: &PanicInfo) -> ! {
 
     loop {
         unsafe { ets_delay_us(500000); };
-        uart::writestring("PANIC\r\n");
+        events::send(Event::Error(AdapterError::Panic, 0));
     }
 }
 
@@ -165,28 +179,164 @@ unsafe extern "C" fn user_rf_cal_sector_set() -> u32
 
 static mut CONNECTED:bool = false;
 static mut CON_CHECK:u8 = 0;
+// The station state of the sdk the board was told last
+static mut WIFI_STATE:u8 = WifiState::Idle as u8;
+static mut ROLE:u8 = ROLE_UNKNOWN;
+// The connections to the other boards, the bytes of the board go to them
+static mut NET:net::Socket<Forwarder<net::Uart>> = net::Socket::new(Forwarder::new(net::Uart));
+// The uart line read so far while waiting for the role
+static mut LINE:[u8; 16] = [0; 16];
+static mut LINE_LEN:usize = 0;
+
+// Reads the role line the board sends at boot, it can come as a command too
+fn read_role(byte: u8) {
+    unsafe {
+        if byte == '\n' as u8 {
+            if let Command::Role(master) = command::parse(&LINE[..LINE_LEN]) {
+                set_role(master);
+            }
+            LINE_LEN = 0;
+        } else if LINE_LEN < LINE.len() {
+            LINE[LINE_LEN] = byte;
+            LINE_LEN += 1;
+        }
+    };
+}
+
+// A byte of the board that is not a command
+fn forward(byte: u8) {
+    unsafe {
+        if ROLE == ROLE_UNKNOWN {
+            read_role(byte);
+        } else {
+            // Dropped while there is no connection to send it to
+            NET.write(byte);
+        }
+    };
+}
+
+fn start_wifi() {
+    unsafe {
+        let con_status;
+        if ROLE == ROLE_CLIENT {
+            con_status = wifi::connect(SETTINGS.ssid(), SETTINGS.password());
+            // The state changes are sent as the update timer sees them
+            WIFI_STATE = WifiState::Idle as u8;
+        } else {
+            con_status = wifi::setup_server(SETTINGS.ssid(), SETTINGS.password(), SETTINGS.channel);
+            if con_status == 0 {
+                events::send(Event::Wifi(WifiState::AccessPoint));
+            }
+        }
+        if con_status != 0 {
+            events::send(Event::Error(AdapterError::Wifi, con_status as u8));
+        }
+    };
+}
+
+// Closes the connections of the role, the next update makes them again
+fn stop_wifi() {
+    unsafe {
+        NET.close();
+        if ROLE == ROLE_CLIENT {
+            wifi::disconnect();
+        }
+        CONNECTED = false;
+        CON_CHECK = 0;
+    };
+}
+
+fn set_role(master: bool) {
+    let role = if master { ROLE_SERVER } else { ROLE_CLIENT };
+    unsafe {
+        // The board repeats the role while it waits for the others
+        if role == ROLE {
+            return;
+        }
+        stop_wifi();
+        ROLE = role;
+    };
+    start_wifi();
+}
+
+fn run_command(command: Command) {
+    let ok = match command {
+        Command::Ssid(_) | Command::Password(_) | Command::Channel(_) | Command::Port(_) => command::set(&command),
+        Command::Role(master) => {
+            set_role(master);
+            true
+        }
+        // Only the master has more than one connection
+        Command::Send(target) => unsafe { ROLE == ROLE_SERVER && NET.route(target.map(|id| id as usize)) },
+        Command::Status => unsafe {
+            match ROLE {
+                ROLE_CLIENT => command::reply_status(control::ROLE_CLIENT, NET.links()),
+                ROLE_SERVER => command::reply_status(control::ROLE_MASTER, NET.links()),
+                _ => command::reply_status(control::ROLE_UNKNOWN, 0),
+            }
+            return;
+        },
+        Command::Reconnect => unsafe {
+            if ROLE != ROLE_UNKNOWN {
+                stop_wifi();
+                start_wifi();
+            }
+            ROLE != ROLE_UNKNOWN
+        },
+        Command::Reboot => {
+            command::reply(true);
+            unsafe { system_restart(); };
+            return;
+        }
+        Command::Invalid => false,
+    };
+    command::reply(ok);
+}
 
 #[no_mangle]
 #[link(name="update")]
 unsafe extern "C" fn update(timer_arg: *const u32) {
 
-    if SERVER_MODE == 0 {
+    let mut byte: u8 = 0;
+
+    // Read chars from the uart, the commands are run and the rest goes to the connection buffer
+    while uart::readchr(&mut byte) {
+        if let Some(command) = command::feed(byte, forward) {
+            run_command(command);
+        }
+    }
+    // The bytes held as the start of a command go on with the rest of their frame once it is
+    // clear they are not one
+    command::flush(forward);
+
+    if ROLE == ROLE_UNKNOWN {
+        return;
+    }
+
+    if byte != 0 {
+        gpio16_output_toggle();
+    }
+
+    if ROLE == ROLE_CLIENT {
         if CON_CHECK == 11 {
             CON_CHECK = 0;
-            if wifi::is_connected() != 5 {
+            let state = wifi::is_connected();
+            if state != WIFI_STATE {
+                WIFI_STATE = state;
+                if let Some(state) = WifiState::from_u8(state) {
+                    events::send(Event::Wifi(state));
+                }
+            }
+            if state != WifiState::Connected as u8 {
                 CONNECTED = false;
-                uart::writestring(".");
                 return;
             } else {
                 if CONNECTED == false {
                     CONNECTED = true; 
-                    client::init();
-                } else {
-                    client::writechr('N' as u8);
-                    client::writechr('O' as u8);
-                    client::writechr('P' as u8);
-                    client::writechr('\n' as u8);
+                    NET.connect(NET_MODE, MASTER_IP, SETTINGS.port);
                 }
+                // The tcp keepalive holds the connection, the board gets the signal strength
+                events::send(Event::Rssi(wifi::rssi()));
             }
         }
         CON_CHECK = CON_CHECK + 1;
@@ -194,38 +344,18 @@ unsafe extern "C" fn update(timer_arg: *const u32) {
         if !CONNECTED {
             return;
         }
-        let mut byte: u8 = 0;
-
-        // Read chars from the uart and push to the tcp-connection buffer
-        while uart::readchr(&mut byte) {
-            uart::writechr(byte);
-            client::writechr(byte);
-        }
-
-        if byte != 0 {
-            gpio16_output_toggle();
-        }
 
         // Send the entire buffer
-        client::sendbuf();
+        NET.flush();
     } else {
         if CONNECTED == false {
-            server::init();
+            NET.listen(NET_MODE, SETTINGS.port);
             CONNECTED = true;
         }
-        let mut byte: u8 = 0;
-
-        // Read chars from the uart and push to the tcp-connection buffer
-        while uart::readchr(&mut byte) {
-            server::writechr(byte);
-        }
-
-        if byte != 0 {
-            gpio16_output_toggle();
-        }
 
         // Send the entire buffer
-        server::sendbuf();
+        NET.flush();
+        NET.poll();
     }
 }
 
@@ -254,22 +384,11 @@ fn user_init() {
     pin_func_select(PERIPHS_IO_MUX_U0TXD_U, FUNC_U0TXD);
     uart::init();
     wifi::init();
+    command::load();
+
+    // The wifi is started once the board has told the role
+    events::send(Event::Boot);
 
-    if SERVER_MODE == 0 { // Client mode
-        uart::writestring("Connecting Wifi\r\n");
-        let con_status = wifi::connect(SSID, PASSWORD);
-        uart::writestring("Status: ");
-        uart::writenum(con_status);
-        uart::writestring("\r\n");
-
-    } else { // Server mode
-        uart::writestring("Setup Wifi server\r\n");
-        let con_status = wifi::setup_server(SSID, PASSWORD);
-        uart::writestring("Status: ");
-        uart::writenum(con_status);
-        uart::writestring("\r\n");        
-    }    
-    
     unsafe {
         let param:u32 = 0;
         ets_timer_setfn(& mut UPDATE_TIMER, update, &param);
@@ -0,0 +1,680 @@
+// The bytes between the uart of the board and the connections. Nothing in here touches the
+// sdk, the connections and the uart are traits so the tests run it with mocks on the host,
+// the simulator includes this file for them.
+
+use crate::control::{ AdapterError, Event, MAX_CONNS };
+use crate::link;
+
+// The bytes of the board sent in one packet, a frame is never split between two
+pub const BUFFER_LEN: usize = 2 * link::MAX_FRAME;
+// The source after the sync and kind of a frame, the payload length after it
+const SOURCE_OFFSET: usize = 2;
+const LEN_OFFSET: usize = 3;
+
+/// What the sdk did with a packet
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Sent {
+    // Gone already, a datagram
+    Done,
+    // Taken, the next packet waits for the sent callback
+    InFlight,
+    // Not taken, it is sent again
+    Refused,
+}
+
+/// The connections, the sdk on the adapter
+pub trait Net {
+    /// Sends the bytes to the connection with the id
+    fn send(&mut self, id: usize, bytes: &[u8]) -> Sent;
+}
+
+/// The uart of the board
+pub trait Board {
+    fn write(&mut self, bytes: &[u8]);
+    fn event(&mut self, event: Event);
+}
+
+/// What happens to the connections, called from the callbacks of the sdk with the id
+/// of the connection
+pub trait Handler {
+    fn connected(&mut self, id: usize);
+    fn received(&mut self, id: usize, data: &[u8]);
+    // The bytes given to the sdk went out, it takes the next ones
+    fn sent<N: Net>(&mut self, id: usize, net: &mut N);
+    fn disconnected(&mut self, id: usize);
+    // A connection more than MAX_CONNS, it is closed again
+    fn refused(&mut self);
+    // The socket was closed, every connection is gone
+    fn closed(&mut self);
+}
+
+// Finds the ends of the frames in a stream of bytes, so the adapter never cuts one. A byte
+// outside of a frame ends on its own, the boards only send frames but the link can garble them
+#[derive(Copy, Clone)]
+struct Frames {
+    // The bytes of the frame so far
+    at: usize,
+    // Its length once the length byte is in, 0 before
+    len: usize,
+}
+
+impl Frames {
+    const fn new() -> Self {
+        Frames { at: 0, len: 0 }
+    }
+
+    // Takes the next byte of the stream, true when it ended a frame
+    fn ended(&mut self, byte: u8) -> bool {
+        self.at += 1;
+        let ended = if self.at == 1 {
+            byte != link::SYNC
+        } else if self.at == LEN_OFFSET + 1 {
+            self.len = link::MAX_FRAME - link::MAX_PAYLOAD + byte as usize;
+            byte as usize > link::MAX_PAYLOAD
+        } else {
+            self.at == self.len
+        };
+        if ended {
+            *self = Frames::new();
+        }
+        ended
+    }
+}
+
+// The end of a frame a connection has not sent yet, the frames go to the uart whole so the
+// control frames of the adapter never land in the middle of one
+#[derive(Copy, Clone)]
+struct Held {
+    bytes: [u8; link::MAX_FRAME],
+    len: usize,
+    frames: Frames,
+}
+
+impl Held {
+    const fn new() -> Self {
+        Held { bytes: [0; link::MAX_FRAME], len: 0, frames: Frames::new() }
+    }
+}
+
+// The frames for a connection waiting for the sdk, it takes one packet at a time and the
+// next one only after the sent callback of the one before
+#[derive(Copy, Clone)]
+struct Outgoing {
+    bytes: [u8; BUFFER_LEN],
+    len: usize,
+    // Given to the sdk, its sent callback has not come yet
+    in_flight: bool,
+}
+
+impl Outgoing {
+    const fn new() -> Self {
+        Outgoing { bytes: [0; BUFFER_LEN], len: 0, in_flight: false }
+    }
+
+    // Queues whole frames, older frames that leave no room for them are dropped. The boards
+    // notice the gap from the sequence numbers and the newest screen is the one to show
+    fn add(&mut self, bytes: &[u8]) {
+        if self.len + bytes.len() > BUFFER_LEN {
+            self.len = 0;
+        }
+        self.bytes[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    // Gives the waiting frames to the sdk unless it still sends the ones before
+    fn push<N: Net>(&mut self, id: usize, net: &mut N) {
+        if self.in_flight || self.len == 0 {
+            return;
+        }
+        match net.send(id, &self.bytes[..self.len]) {
+            Sent::Done => self.len = 0,
+            Sent::InFlight => {
+                self.in_flight = true;
+                self.len = 0;
+            }
+            Sent::Refused => {}
+        }
+    }
+}
+
+pub struct Forwarder<B> {
+    board: B,
+    open: [bool; MAX_CONNS],
+    // The connection the last bytes written to the uart came from
+    last_source: Option<usize>,
+    // The connection the buffer is sent to, None for all of them
+    target: Option<usize>,
+    buffer: [u8; BUFFER_LEN],
+    len: usize,
+    // Where the frame being written starts, the bytes before it are whole frames
+    frame_start: usize,
+    frames: Frames,
+    // By connection
+    held: [Held; MAX_CONNS],
+    outgoing: [Outgoing; MAX_CONNS],
+}
+
+impl<B> Forwarder<B> {
+    pub const fn new(board: B) -> Self {
+        Forwarder {
+            board,
+            open: [false; MAX_CONNS],
+            last_source: None,
+            target: None,
+            buffer: [0; BUFFER_LEN],
+            len: 0,
+            frame_start: 0,
+            frames: Frames::new(),
+            held: [Held::new(); MAX_CONNS],
+            outgoing: [Outgoing::new(); MAX_CONNS],
+        }
+    }
+
+    /// A byte of the board, the whole frames are sent once another one might not fit
+    pub fn write<N: Net>(&mut self, byte: u8, net: &mut N) {
+        self.buffer[self.len] = byte;
+        self.len += 1;
+        if self.frames.ended(byte) {
+            self.frame_start = self.len;
+            if self.frame_start > BUFFER_LEN - link::MAX_FRAME {
+                self.flush(net);
+            }
+        }
+    }
+
+    /// Sends the whole frames of the buffer, the one being written is kept for the next packet.
+    /// The frames the sdk did not take are sent again
+    pub fn flush<N: Net>(&mut self, net: &mut N) {
+        self.send(self.frame_start, net);
+        self.buffer.copy_within(self.frame_start..self.len, 0);
+        self.len -= self.frame_start;
+        self.frame_start = 0;
+    }
+
+    // Sends the first bytes of the buffer to the open connections, or to the one routed to.
+    // Without any they are dropped, there is nothing to send them to before the wifi is up
+    fn send<N: Net>(&mut self, len: usize, net: &mut N) {
+        for (id, outgoing) in self.outgoing.iter_mut().enumerate() {
+            if !self.open[id] {
+                continue;
+            }
+            if len != 0 && (self.target.is_none() || self.target == Some(id)) {
+                outgoing.add(&self.buffer[..len]);
+            }
+            outgoing.push(id, net);
+        }
+    }
+
+    /// Sends the next bytes to one connection, or to all of them with None
+    pub fn route<N: Net>(&mut self, target: Option<usize>, net: &mut N) -> bool {
+        match target {
+            Some(id) if id >= MAX_CONNS => false,
+            _ => {
+                // The bytes so far go where they were meant to, the board sends the
+                // command between two frames
+                self.send(self.len, net);
+                self.len = 0;
+                self.frame_start = 0;
+                self.frames = Frames::new();
+                self.target = target;
+                true
+            }
+        }
+    }
+
+    pub fn links(&self) -> usize {
+        self.open.iter().filter(|open| **open).count()
+    }
+
+    #[cfg(test)]
+    pub fn board(&mut self) -> &mut B {
+        &mut self.board
+    }
+}
+
+// A frame sent with the id of the adapter, the board would take it for a control frame
+fn posing_as_adapter(frame: &[u8]) -> bool {
+    frame.len() > SOURCE_OFFSET && frame[0] == link::SYNC && frame[SOURCE_OFFSET] == link::ADAPTER_SOURCE
+}
+
+impl<B: Board> Forwarder<B> {
+    // Writes whole frames of the connection to the uart, after its id when it is not the
+    // one of the last bytes
+    fn write_frames(&mut self, id: usize, bytes: &[u8]) {
+        if self.last_source != Some(id) {
+            self.last_source = Some(id);
+            self.board.event(Event::Source(id as u8));
+        }
+        self.board.write(bytes);
+    }
+}
+
+impl<B: Board> Handler for Forwarder<B> {
+    fn connected(&mut self, id: usize) {
+        if id < MAX_CONNS {
+            self.open[id] = true;
+            self.held[id] = Held::new();
+            self.outgoing[id] = Outgoing::new();
+            self.board.event(Event::LinkUp(id as u8));
+            // A udp client can take the id of another one
+            if self.last_source == Some(id) {
+                self.last_source = None;
+            }
+        }
+    }
+
+    // Writes the whole frames to the uart, the end of a frame cut by the packet is held
+    // until the rest of it arrives. Only this adapter sends as the adapter, such a frame
+    // from a connection is dropped
+    fn received(&mut self, id: usize, data: &[u8]) {
+        if id >= MAX_CONNS || !self.open[id] {
+            return;
+        }
+        let mut rest = data;
+        // The frame cut by the last packet goes first
+        while self.held[id].len != 0 {
+            let (byte, more) = match rest.split_first() {
+                Some(split) => split,
+                None => return,
+            };
+            rest = more;
+            let held = &mut self.held[id];
+            held.bytes[held.len] = *byte;
+            held.len += 1;
+            if held.frames.ended(*byte) {
+                let Held { bytes, len, .. } = *held;
+                held.len = 0;
+                if !posing_as_adapter(&bytes[..len]) {
+                    self.write_frames(id, &bytes[..len]);
+                }
+            }
+        }
+        // The frames up to whole are read, the ones from written on are not written yet
+        let mut written = 0;
+        let mut whole = 0;
+        for (i, byte) in rest.iter().enumerate() {
+            if self.held[id].frames.ended(*byte) {
+                if posing_as_adapter(&rest[whole..=i]) {
+                    if whole != written {
+                        self.write_frames(id, &rest[written..whole]);
+                    }
+                    written = i + 1;
+                }
+                whole = i + 1;
+            }
+        }
+        if whole != written {
+            self.write_frames(id, &rest[written..whole]);
+        }
+        let held = &mut self.held[id];
+        held.len = rest.len() - whole;
+        held.bytes[..held.len].copy_from_slice(&rest[whole..]);
+    }
+
+    fn sent<N: Net>(&mut self, id: usize, net: &mut N) {
+        if id < MAX_CONNS && self.open[id] {
+            self.outgoing[id].in_flight = false;
+            self.outgoing[id].push(id, net);
+        }
+    }
+
+    // The GD32 pauses the game until the players are heard again
+    fn disconnected(&mut self, id: usize) {
+        if id < MAX_CONNS && self.open[id] {
+            self.open[id] = false;
+            self.held[id] = Held::new();
+            self.outgoing[id] = Outgoing::new();
+            self.board.event(Event::LinkDown(id as u8));
+            if self.last_source == Some(id) {
+                self.last_source = None;
+            }
+        }
+    }
+
+    fn refused(&mut self) {
+        self.board.event(Event::Error(AdapterError::TooManyConns, 0));
+    }
+
+    // The board hears of every connection that was up, the buffer and the target are forgotten
+    fn closed(&mut self) {
+        for id in 0..MAX_CONNS {
+            self.disconnected(id);
+        }
+        self.last_source = None;
+        self.target = None;
+        self.len = 0;
+        self.frame_start = 0;
+        self.frames = Frames::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What the adapter wrote to the uart of the board
+    #[derive(Debug, PartialEq)]
+    enum Out {
+        Bytes(Vec<u8>),
+        Event(String),
+    }
+
+    #[derive(Default)]
+    struct MockBoard {
+        out: Vec<Out>,
+    }
+
+    impl Board for MockBoard {
+        fn write(&mut self, bytes: &[u8]) {
+            self.out.push(Out::Bytes(bytes.to_vec()));
+        }
+
+        fn event(&mut self, event: Event) {
+            self.out.push(Out::Event(format!("{:?}", event)));
+        }
+    }
+
+    // The packets the sdk took by connection id, a busy one takes none. Tcp packets wait
+    // for the sent callback, datagrams do not
+    #[derive(Default)]
+    struct MockNet {
+        sent: Vec<(usize, Vec<u8>)>,
+        busy: bool,
+        datagrams: bool,
+    }
+
+    impl Net for MockNet {
+        fn send(&mut self, id: usize, bytes: &[u8]) -> Sent {
+            if self.busy {
+                return Sent::Refused;
+            }
+            self.sent.push((id, bytes.to_vec()));
+            if self.datagrams { Sent::Done } else { Sent::InFlight }
+        }
+    }
+
+    fn event(event: Event) -> Out {
+        Out::Event(format!("{:?}", event))
+    }
+
+    fn bytes(bytes: &[u8]) -> Out {
+        Out::Bytes(bytes.to_vec())
+    }
+
+    fn write(forwarder: &mut Forwarder<MockBoard>, data: &[u8], net: &mut MockNet) {
+        for byte in data.iter() {
+            forwarder.write(*byte, net);
+        }
+    }
+
+    // The sent callbacks of every connection, the sdk takes the next packets after them
+    fn deliver(forwarder: &mut Forwarder<MockBoard>, net: &mut MockNet) {
+        for id in 0..MAX_CONNS {
+            forwarder.sent(id, net);
+        }
+    }
+
+    // Frames with payloads of the lengths, full of sync bytes
+    fn frames(lens: &[usize]) -> Vec<u8> {
+        let mut encoder = link::Encoder::new(0);
+        let mut data = Vec::new();
+        for len in lens.iter() {
+            let mut frame = [0u8; link::MAX_FRAME];
+            let n = encoder.encode(link::FrameKind::ScreenResync, &vec![link::SYNC; *len], &mut frame).unwrap();
+            data.extend_from_slice(&frame[..n]);
+        }
+        data
+    }
+
+    // The frames of a packet, none of them is cut if they all decode on their own
+    fn whole_frames(packet: &[u8]) -> usize {
+        let mut count = 0;
+        link::Decoder::new().feed(packet, |frame| {
+            if frame.is_ok() {
+                count += 1;
+            }
+        });
+        count
+    }
+
+    // A forwarder with the connections up, the events of connecting them taken out
+    fn connected(ids: &[usize]) -> Forwarder<MockBoard> {
+        let mut forwarder = Forwarder::new(MockBoard::default());
+        for id in ids.iter() {
+            forwarder.connected(*id);
+        }
+        forwarder.board().out.clear();
+        forwarder
+    }
+
+    #[test]
+    fn nothing_is_sent_without_links() {
+        // A client before its connection is made has nothing to send to
+        let mut forwarder = Forwarder::new(MockBoard::default());
+        let mut net = MockNet::default();
+        write(&mut forwarder, b"early", &mut net);
+        forwarder.flush(&mut net);
+        assert!(net.sent.is_empty());
+        assert_eq!(forwarder.links(), 0);
+    }
+
+    #[test]
+    fn received_bytes_come_after_their_source() {
+        let mut forwarder = Forwarder::new(MockBoard::default());
+        forwarder.connected(0);
+        forwarder.connected(2);
+        forwarder.received(0, b"ab");
+        forwarder.received(0, b"cd");
+        forwarder.received(2, b"ef");
+        forwarder.received(0, b"gh");
+        forwarder.received(1, b"not up");
+        assert_eq!(forwarder.board().out, vec![
+            event(Event::LinkUp(0)), event(Event::LinkUp(2)),
+            event(Event::Source(0)), bytes(b"ab"), bytes(b"cd"),
+            event(Event::Source(2)), bytes(b"ef"),
+            event(Event::Source(0)), bytes(b"gh"),
+        ]);
+        assert_eq!(forwarder.links(), 2);
+    }
+
+    #[test]
+    fn a_cut_frame_waits_for_the_rest() {
+        // The frames of another connection go first
+        let mut forwarder = connected(&[0, 2]);
+        let one = frames(&[30]);
+        let two = frames(&[5]);
+        forwarder.received(0, &one[..10]);
+        forwarder.received(2, &two);
+        forwarder.received(0, &one[10..]);
+        assert_eq!(forwarder.board().out, vec![
+            event(Event::Source(2)), bytes(&two),
+            event(Event::Source(0)), bytes(&one),
+        ]);
+    }
+
+    #[test]
+    fn frames_with_the_adapter_source_are_dropped() {
+        // The frames around them get through
+        let mut forwarder = connected(&[0, 2]);
+        let one = frames(&[30]);
+        let mut posing = frames(&[4]);
+        posing[SOURCE_OFFSET] = link::ADAPTER_SOURCE;
+        let after = frames(&[6]);
+        forwarder.received(0, &[&one[..], &posing[..9]].concat());
+        forwarder.received(0, &[&posing[9..], &after[..], &posing[..]].concat());
+        forwarder.received(2, &posing);
+        assert_eq!(forwarder.board().out, vec![event(Event::Source(0)), bytes(&one), bytes(&after)]);
+    }
+
+    #[test]
+    fn the_board_bytes_go_to_every_link() {
+        let mut forwarder = connected(&[0, 2]);
+        let mut net = MockNet::default();
+        write(&mut forwarder, b"all", &mut net);
+        forwarder.flush(&mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![(0, b"all".to_vec()), (2, b"all".to_vec())]);
+    }
+
+    #[test]
+    fn packets_hold_whole_frames() {
+        // Whole frames go out once another one might not fit, the rest with the flush
+        let mut forwarder = connected(&[0, 2]);
+        let mut net = MockNet::default();
+        let lens = [link::MAX_PAYLOAD, 3, 60, link::MAX_PAYLOAD, 0, 100, 17];
+        let data = frames(&lens);
+        // A fast sdk, every packet went out before the next one
+        for byte in data.iter() {
+            forwarder.write(*byte, &mut net);
+            deliver(&mut forwarder, &mut net);
+        }
+        assert!(!net.sent.is_empty());
+        forwarder.flush(&mut net);
+        let to_0: Vec<Vec<u8>> = net.sent.iter().filter(|(id, _)| *id == 0).map(|(_, b)| b.clone()).collect();
+        assert!(to_0.len() > 1);
+        assert!(to_0.iter().all(|packet| packet.len() <= BUFFER_LEN));
+        assert_eq!(to_0.concat(), data);
+        assert_eq!(to_0.iter().map(|packet| whole_frames(packet)).sum::<usize>(), lens.len());
+    }
+
+    #[test]
+    fn a_frame_cut_by_the_flush_waits_for_the_rest() {
+        let mut forwarder = connected(&[0, 2]);
+        let mut net = MockNet::default();
+        let data = frames(&[40]);
+        write(&mut forwarder, &data[..20], &mut net);
+        forwarder.flush(&mut net);
+        assert!(net.sent.is_empty());
+        write(&mut forwarder, &data[20..], &mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![(0, data.clone()), (2, data)]);
+    }
+
+    #[test]
+    fn the_next_packet_waits_for_the_sent_callback() {
+        let mut forwarder = connected(&[0]);
+        let mut net = MockNet::default();
+        let first = frames(&[10]);
+        let second = frames(&[20, 30]);
+        write(&mut forwarder, &first, &mut net);
+        forwarder.flush(&mut net);
+        write(&mut forwarder, &second[..27], &mut net);
+        forwarder.flush(&mut net);
+        write(&mut forwarder, &second[27..], &mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![(0, first.clone())]);
+        forwarder.sent(0, &mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![(0, first), (0, second)]);
+    }
+
+    #[test]
+    fn datagrams_do_not_wait() {
+        let mut forwarder = connected(&[0]);
+        let mut net = MockNet { datagrams: true, ..MockNet::default() };
+        let first = frames(&[10]);
+        let second = frames(&[20]);
+        write(&mut forwarder, &first, &mut net);
+        forwarder.flush(&mut net);
+        write(&mut forwarder, &second, &mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![(0, first), (0, second)]);
+    }
+
+    #[test]
+    fn frames_the_sdk_did_not_take_are_sent_again() {
+        let mut forwarder = connected(&[0]);
+        let mut net = MockNet { busy: true, ..MockNet::default() };
+        let data = frames(&[10]);
+        write(&mut forwarder, &data, &mut net);
+        forwarder.flush(&mut net);
+        net.busy = false;
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![(0, data)]);
+    }
+
+    #[test]
+    fn newer_frames_replace_the_ones_that_do_not_fit() {
+        let mut forwarder = connected(&[0]);
+        let mut net = MockNet::default();
+        let first = frames(&[10]);
+        write(&mut forwarder, &first, &mut net);
+        forwarder.flush(&mut net);
+        let old = frames(&[link::MAX_PAYLOAD]);
+        let new = frames(&[link::MAX_PAYLOAD, 50]);
+        for data in [&old, &new].iter() {
+            write(&mut forwarder, data, &mut net);
+            forwarder.flush(&mut net);
+        }
+        forwarder.sent(0, &mut net);
+        assert_eq!(net.sent, vec![(0, first), (0, new)]);
+    }
+
+    #[test]
+    fn a_route_sends_the_next_bytes_to_one_link() {
+        // The bytes before a route go where they were meant to
+        let mut forwarder = connected(&[0, 2]);
+        let mut net = MockNet::default();
+        write(&mut forwarder, b"before", &mut net);
+        assert!(forwarder.route(Some(2), &mut net));
+        deliver(&mut forwarder, &mut net);
+        write(&mut forwarder, b"after", &mut net);
+        forwarder.flush(&mut net);
+        deliver(&mut forwarder, &mut net);
+        assert!(!forwarder.route(Some(MAX_CONNS), &mut net));
+        assert!(forwarder.route(None, &mut net));
+        write(&mut forwarder, b"again", &mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(net.sent, vec![
+            (0, b"before".to_vec()), (2, b"before".to_vec()),
+            (2, b"after".to_vec()),
+            (0, b"again".to_vec()), (2, b"again".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn a_lost_link_is_told_once() {
+        // The next bytes of its id come after the id again
+        let mut forwarder = connected(&[0, 2]);
+        let mut net = MockNet::default();
+        forwarder.received(0, b"first");
+        forwarder.board().out.clear();
+        forwarder.disconnected(0);
+        forwarder.disconnected(0);
+        forwarder.received(0, b"gone");
+        write(&mut forwarder, b"left", &mut net);
+        forwarder.flush(&mut net);
+        forwarder.connected(0);
+        forwarder.received(0, b"back");
+        assert_eq!(forwarder.board().out, vec![
+            event(Event::LinkDown(0)), event(Event::LinkUp(0)),
+            event(Event::Source(0)), bytes(b"back"),
+        ]);
+        assert_eq!(net.sent, vec![(2, b"left".to_vec())]);
+    }
+
+    #[test]
+    fn a_link_too_many_is_refused() {
+        let mut forwarder = Forwarder::new(MockBoard::default());
+        forwarder.refused();
+        assert_eq!(forwarder.board().out, vec![event(Event::Error(AdapterError::TooManyConns, 0))]);
+    }
+
+    #[test]
+    fn closing_forgets_the_links_the_target_and_the_buffer() {
+        let mut forwarder = connected(&[2]);
+        let mut net = MockNet::default();
+        forwarder.route(Some(2), &mut net);
+        write(&mut forwarder, b"dropped", &mut net);
+        forwarder.closed();
+        forwarder.connected(1);
+        forwarder.received(1, b"new");
+        write(&mut forwarder, b"fresh", &mut net);
+        forwarder.flush(&mut net);
+        assert_eq!(forwarder.links(), 1);
+        assert_eq!(net.sent, vec![(1, b"fresh".to_vec())]);
+        assert_eq!(forwarder.board().out, vec![
+            event(Event::LinkDown(2)), event(Event::LinkUp(1)), event(Event::Source(1)), bytes(b"new"),
+        ]);
+    }
+}
@@ -0,0 +1,475 @@
+// The connections to the other boards. The sdk keeps pointers to the espconn structs it
+// was given and calls back with them, so a Socket keeps those structs together with the
+// handler of the callbacks and has to stay in place once it is open. Its espconn points
+// back to it in the reverse field, which the sdk copies into the espconn of every tcp
+// connection it accepts, and the extern "C" functions at the end find the socket through
+// it, the reconnect timer gets it as its argument. The callbacks and the timers run one
+// after the other, never at the same time.
+
+mod sdk;
+pub mod forward;
+
+use core::{ ptr, slice };
+
+use crate::control::{ Event, MAX_CONNS };
+use crate::events;
+use crate::uart;
+use crate::{ ets_timer_arm, ets_timer_disarm, ets_timer_setfn, os_timer_t };
+use forward::{ Board, Forwarder, Handler, Net, Sent };
+use sdk::*;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mode {
+    Tcp,
+    // Datagrams instead of a tcp connection, the adapters of all the boards have to use the same
+    #[allow(dead_code)] // picked with NET_MODE in main.rs
+    Udp,
+}
+
+// How long a client waits before it connects again, ms
+const RECONNECT_DELAY: u32 = 1000;
+
+// A udp client heard from, the address and port the replies go to
+type Peer = ([u8; 4], i32);
+
+/// Writes to the uart of the board
+pub struct Uart;
+
+impl Board for Uart {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes.iter() {
+            uart::writechr(*byte);
+        }
+    }
+
+    fn event(&mut self, event: Event) {
+        events::send(event);
+    }
+}
+
+// The espconn structs the sdk points to and the connections made with them
+struct Conns {
+    conn: espconn,
+    tcp: esp_tcp,
+    udp: esp_udp,
+    mode: Mode,
+    master: bool,
+    // Closed on purpose, the connection is not made again when it is lost
+    stopped: bool,
+    // The espconn exists in the sdk and has to be deleted before it is made again
+    created: bool,
+    // The tcp connections by id, the one to the master is 0
+    conns: [*mut espconn; MAX_CONNS],
+    // The address and port of every tcp client, the sdk tells a lost one by them
+    remotes: [(u32, u32); MAX_CONNS],
+    // The addresses datagrams came from by id
+    peers: [Option<Peer>; MAX_CONNS],
+    // The datagram count when each peer was last heard, the quietest is replaced by a new one
+    heard: [u32; MAX_CONNS],
+    datagrams: u32,
+    // A fifth connection, closed in the next poll as the sdk does not allow it in a callback
+    refused: *mut espconn,
+}
+
+impl Conns {
+    const fn new() -> Self {
+        Conns {
+            conn: espconn {
+                conn_type: espconn_type::ESPCONN_INVALID as u32,
+                state: espconn_state::ESPCONN_NONE as u32,
+                proto: ptr::null_mut(),
+                recv_callback: None,
+                sent_callback: None,
+                link_cnt: 0,
+                reverse: ptr::null_mut(),
+            },
+            tcp: esp_tcp {
+                remote_port: 0,
+                local_port: 0,
+                local_ip: 0,
+                remote_ip: 0,
+                connect_callback: None,
+                reconnect_callback: None,
+                disconnect_callback: None,
+                write_finish_fn: None,
+            },
+            udp: esp_udp {
+                remote_port: 0,
+                local_port: 0,
+                local_ip: [0; 4],
+                remote_ip: [0; 4],
+            },
+            mode: Mode::Tcp,
+            master: false,
+            stopped: true,
+            created: false,
+            conns: [ptr::null_mut(); MAX_CONNS],
+            remotes: [(0, 0); MAX_CONNS],
+            peers: [None; MAX_CONNS],
+            heard: [0; MAX_CONNS],
+            datagrams: 0,
+            refused: ptr::null_mut(),
+        }
+    }
+
+    fn close(&mut self) {
+        self.stopped = true;
+        for slot in self.conns.iter_mut() {
+            if !slot.is_null() {
+                unsafe { espconn_disconnect(*slot); };
+                *slot = ptr::null_mut();
+            }
+        }
+        if self.created {
+            unsafe { espconn_delete(&mut self.conn); };
+            self.created = false;
+        }
+        self.remotes = [(0, 0); MAX_CONNS];
+        self.peers = [None; MAX_CONNS];
+        self.heard = [0; MAX_CONNS];
+        self.datagrams = 0;
+    }
+
+    fn id(&self, conn: *mut espconn) -> Option<usize> {
+        self.conns.iter().position(|c| *c == conn)
+    }
+
+    // The client that left, the sdk put its address in the listening espconn
+    fn remote_id(&self) -> Option<usize> {
+        let remote = (self.tcp.remote_ip, self.tcp.remote_port);
+        (0..MAX_CONNS).find(|id| !self.conns[*id].is_null() && self.remotes[*id] == remote)
+    }
+
+    // The id of the udp client the last datagram came from and whether it is new, a new
+    // one takes a free id or the one of the client heard from the longest time ago
+    fn peer(&mut self) -> Option<(usize, bool)> {
+        let mut info: *mut remot_info = ptr::null_mut();
+        let peer = unsafe {
+            if espconn_get_connection_info(&mut self.conn, &mut info, 0) != 0 || info.is_null() {
+                return None;
+            }
+            Some(((*info).remote_ip, (*info).remote_port))
+        };
+        self.datagrams = self.datagrams.wrapping_add(1);
+        if let Some(id) = self.peers.iter().position(|p| *p == peer) {
+            self.heard[id] = self.datagrams;
+            return Some((id, false));
+        }
+        let id = match self.peers.iter().position(|p| p.is_none()) {
+            Some(id) => id,
+            // Most likely a client that came back from another port, its old one went quiet
+            None => (0..MAX_CONNS).max_by_key(|id| self.datagrams.wrapping_sub(self.heard[*id]))?,
+        };
+        self.peers[id] = peer;
+        self.heard[id] = self.datagrams;
+        Some((id, true))
+    }
+}
+
+impl Net for Conns {
+    fn send(&mut self, id: usize, bytes: &[u8]) -> Sent {
+        if id >= MAX_CONNS {
+            return Sent::Refused;
+        }
+        let conn: *mut espconn = match self.mode {
+            Mode::Udp if self.master => match self.peers[id] {
+                Some((ip, port)) => {
+                    self.udp.remote_ip = ip;
+                    self.udp.remote_port = port;
+                    &mut self.conn
+                }
+                None => return Sent::Refused,
+            },
+            Mode::Udp if self.created && id == 0 => &mut self.conn,
+            Mode::Udp => return Sent::Refused,
+            Mode::Tcp => self.conns[id],
+        };
+        if conn.is_null() || unsafe { espconn_send(conn, bytes.as_ptr(), bytes.len() as u16) } != 0 {
+            return Sent::Refused;
+        }
+        // The sdk copied the datagram, a tcp connection takes no more until the sent callback
+        match self.mode {
+            Mode::Udp => Sent::Done,
+            Mode::Tcp => Sent::InFlight,
+        }
+    }
+}
+
+/// The socket of the adapter, listening on the master and connected to it on the others,
+/// with the handler its connections are reported to. The sdk points into it once it is open
+/// so it has to stay where it is, the adapter keeps it in a static
+pub struct Socket<H> {
+    conns: Conns,
+    handler: H,
+    // Set up the first time the client connects again
+    timer: Option<os_timer_t>,
+}
+
+impl<H: Handler> Socket<H> {
+    pub const fn new(handler: H) -> Self {
+        Socket { conns: Conns::new(), handler, timer: None }
+    }
+
+    /// Listens for the other boards on the port, the master
+    pub fn listen(&mut self, mode: Mode, port: u16) {
+        self.open(mode, true, [0; 4], port);
+    }
+
+    /// Connects to the master at the address
+    pub fn connect(&mut self, mode: Mode, ip: [u8; 4], port: u16) {
+        self.open(mode, false, ip, port);
+    }
+
+    /// Closes the connections and the socket before the wifi is set up again
+    pub fn close(&mut self) {
+        if let Some(timer) = self.timer.as_mut() {
+            unsafe { ets_timer_disarm(timer); };
+        }
+        self.conns.close();
+        self.handler.closed();
+    }
+
+    /// Closes a connection that did not fit, called from the update timer
+    pub fn poll(&mut self) {
+        if !self.conns.refused.is_null() {
+            unsafe { espconn_disconnect(self.conns.refused); };
+            self.conns.refused = ptr::null_mut();
+        }
+    }
+
+    fn open(&mut self, mode: Mode, master: bool, ip: [u8; 4], port: u16) {
+        self.close();
+        let socket = self as *mut Self as *mut u32;
+        let conns = &mut self.conns;
+        conns.mode = mode;
+        conns.master = master;
+        conns.stopped = false;
+        conns.conn.state = espconn_state::ESPCONN_NONE as u32;
+        conns.conn.reverse = socket;
+
+        match mode {
+            Mode::Udp => {
+                if master {
+                    conns.udp.local_port = port as i32;
+                } else {
+                    conns.udp.local_port = unsafe { espconn_port() } as i32;
+                    conns.udp.remote_port = port as i32;
+                    conns.udp.remote_ip = ip;
+                }
+                conns.conn.conn_type = espconn_type::ESPCONN_UDP as u32;
+                conns.conn.proto = &mut conns.udp as *mut esp_udp as *mut esp_tcp;
+                // The datagrams are done once sent, there is no sent callback to wait for
+                unsafe {
+                    espconn_regist_recvcb(&mut conns.conn, on_recv_udp::<H>);
+                    conns.created = espconn_create(&mut conns.conn) == 0;
+                };
+                // Nothing to wait for, the datagrams can be sent right away
+                if conns.created && !master {
+                    self.handler.connected(0);
+                }
+            }
+            Mode::Tcp => {
+                if master {
+                    conns.tcp.local_port = port as u32;
+                } else {
+                    conns.tcp.remote_port = port as u32;
+                    conns.tcp.remote_ip = u32::from_le_bytes(ip);
+                }
+                conns.conn.conn_type = espconn_type::ESPCONN_TCP as u32;
+                conns.conn.proto = &mut conns.tcp;
+                unsafe {
+                    espconn_regist_connectcb(&mut conns.conn, on_connect::<H>);
+                    if master {
+                        conns.created = espconn_accept(&mut conns.conn) == 0;
+                    } else {
+                        espconn_regist_disconcb(&mut conns.conn, on_disconnect::<H>);
+                        espconn_connect(&mut conns.conn);
+                    }
+                };
+            }
+        }
+    }
+
+    // A tcp connection was made, the master gets a new espconn for every client
+    fn connected(&mut self, conn: *mut espconn) {
+        let id = if self.conns.master {
+            self.conns.conns.iter().position(|c| c.is_null())
+        } else {
+            Some(0)
+        };
+        let id = match id {
+            Some(id) => id,
+            None => {
+                self.conns.refused = conn;
+                self.handler.refused();
+                return;
+            }
+        };
+        self.conns.conns[id] = conn;
+
+        unsafe {
+            self.conns.remotes[id] = ((*(*conn).proto).remote_ip, (*(*conn).proto).remote_port);
+            keep_alive(conn);
+            espconn_regist_recvcb(conn, on_recv::<H>);
+            espconn_regist_sentcb(conn, on_sent::<H>);
+            espconn_regist_disconcb(conn, on_disconnect::<H>);
+        };
+        self.handler.connected(id);
+    }
+
+    fn received(&mut self, conn: *mut espconn, data: &[u8]) {
+        if let Some(id) = self.conns.id(conn) {
+            self.handler.received(id, data);
+        }
+    }
+
+    fn received_udp(&mut self, data: &[u8]) {
+        let id = if self.conns.master {
+            match self.conns.peer() {
+                Some((id, new)) => {
+                    // A new client, the same as a tcp connection for the board
+                    if new {
+                        self.handler.connected(id);
+                    }
+                    id
+                }
+                None => return,
+            }
+        } else {
+            0
+        };
+        self.handler.received(id, data);
+    }
+
+    fn sent(&mut self, conn: *mut espconn) {
+        if let Some(id) = self.conns.id(conn) {
+            self.handler.sent(id, &mut self.conns);
+        }
+    }
+
+    // The espconn of a lost connection can be freed already, the stored ones are only compared
+    fn disconnected(&mut self, conn: *mut espconn) {
+        let own: *mut espconn = &mut self.conns.conn;
+        let id = match self.conns.id(conn) {
+            Some(id) => Some(id),
+            // The master gets its listening espconn back instead of the one of the client
+            None if conn == own && self.conns.master => self.conns.remote_id(),
+            None => None,
+        };
+        if let Some(id) = id {
+            self.conns.conns[id] = ptr::null_mut();
+            self.handler.disconnected(id);
+        }
+        if !self.conns.master && !self.conns.stopped {
+            self.reconnect_later();
+        }
+    }
+
+    // The sdk does not allow connecting in its callbacks, the timer does it
+    fn reconnect_later(&mut self) {
+        let socket = self as *mut Self as *const u32;
+        let timer = self.timer.get_or_insert(os_timer_t {
+            timer_next: ptr::null_mut(),
+            timer_expire: 0,
+            timer_period: 0,
+            timer_func: on_reconnect::<H>,
+            timer_arg: socket,
+        });
+        unsafe {
+            ets_timer_disarm(timer);
+            ets_timer_setfn(timer, on_reconnect::<H>, socket);
+            ets_timer_arm(timer, RECONNECT_DELAY, 0);
+        };
+    }
+
+    fn reconnect(&mut self) {
+        if !self.conns.master && !self.conns.stopped {
+            unsafe { espconn_connect(&mut self.conns.conn); };
+        }
+    }
+}
+
+impl<B: Board> Socket<Forwarder<B>> {
+    /// A byte of the board for the connections
+    pub fn write(&mut self, byte: u8) {
+        self.handler.write(byte, &mut self.conns);
+    }
+
+    /// Sends the bytes written so far
+    pub fn flush(&mut self) {
+        self.handler.flush(&mut self.conns);
+    }
+
+    /// Sends the next bytes to one connection, or to all of them with None
+    pub fn route(&mut self, target: Option<usize>) -> bool {
+        self.handler.route(target, &mut self.conns)
+    }
+
+    /// The connections up, the clients heard from with udp
+    pub fn links(&self) -> usize {
+        self.handler.links()
+    }
+}
+
+unsafe fn keep_alive(conn: *mut espconn) {
+    let mut keep_alive: u32 = 1;
+    espconn_set_opt(conn, ESPCONN_KEEPALIVE);
+    espconn_set_keepalive(conn, espconn_level::ESPCONN_KEEPIDLE as u32, &keep_alive);
+    keep_alive = 5; //repeat interval = 5s
+    espconn_set_keepalive(conn, espconn_level::ESPCONN_KEEPINTVL as u32, &keep_alive);
+    keep_alive = 2; //repeat 2times
+    espconn_set_keepalive(conn, espconn_level::ESPCONN_KEEPCNT as u32, &keep_alive);
+}
+
+// The socket an espconn of the sdk belongs to, none before it was opened
+unsafe fn socket<'a, H>(conn: *mut espconn) -> Option<&'a mut Socket<H>> {
+    if conn.is_null() {
+        return None;
+    }
+    ((*conn).reverse as *mut Socket<H>).as_mut()
+}
+
+// The bytes the sdk received, only valid in the callback
+unsafe fn received<'a>(data: *const u8, len: u16) -> &'a [u8] {
+    if data.is_null() {
+        &[]
+    } else {
+        slice::from_raw_parts(data, len as usize)
+    }
+}
+
+unsafe extern "C" fn on_connect<H: Handler>(conn: *mut espconn) {
+    if let Some(socket) = socket::<H>(conn) {
+        socket.connected(conn);
+    }
+}
+
+unsafe extern "C" fn on_recv<H: Handler>(conn: *mut espconn, data: *const u8, len: u16) {
+    if let Some(socket) = socket::<H>(conn) {
+        socket.received(conn, received(data, len));
+    }
+}
+
+unsafe extern "C" fn on_recv_udp<H: Handler>(conn: *mut espconn, data: *const u8, len: u16) {
+    if let Some(socket) = socket::<H>(conn) {
+        socket.received_udp(received(data, len));
+    }
+}
+
+unsafe extern "C" fn on_sent<H: Handler>(conn: *mut espconn) {
+    if let Some(socket) = socket::<H>(conn) {
+        socket.sent(conn);
+    }
+}
+
+unsafe extern "C" fn on_disconnect<H: Handler>(conn: *mut espconn) {
+    if let Some(socket) = socket::<H>(conn) {
+        socket.disconnected(conn);
+    }
+}
+
+unsafe extern "C" fn on_reconnect<H: Handler>(arg: *const u32) {
+    if let Some(socket) = (arg as *mut Socket<H>).as_mut() {
+        socket.reconnect();
+    }
+}
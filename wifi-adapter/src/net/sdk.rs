@@ -0,0 +1,103 @@
+#![allow(dead_code)]
+#![allow(non_camel_case_types)]
+
+// The espconn api of the sdk, only used by the net module
+
+// Function pointer types used
+pub type espconn_connect_callback = unsafe extern "C" fn(arg: *mut espconn);
+pub type espconn_reconnect_callback = unsafe extern "C" fn(arg: *mut espconn, err: i32);
+pub type espconn_recv_callback = unsafe extern "C" fn(arg: *mut espconn, data: *const u8, len: u16);
+pub type espconn_sent_callback = unsafe extern "C" fn(arg: *mut espconn);
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct esp_tcp {
+    pub remote_port: u32,
+    pub local_port: u32,
+    pub local_ip: u32,
+    pub remote_ip: u32,
+    pub connect_callback: Option<espconn_connect_callback>,
+    pub reconnect_callback: Option<espconn_reconnect_callback>,
+    pub disconnect_callback: Option<espconn_connect_callback>,
+    pub write_finish_fn: Option<espconn_connect_callback>,
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct esp_udp {
+    pub remote_port: i32,
+    pub local_port: i32,
+    pub local_ip: [u8; 4],
+    pub remote_ip: [u8; 4],
+}
+
+// The sender of the last datagram
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct remot_info {
+    pub state: u32,
+    pub remote_port: i32,
+    pub remote_ip: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct espconn {
+    /** type of the espconn (TCP, UDP) */
+    pub conn_type: u32,
+    /** current state of the espconn */
+    pub state: u32,
+    // The sdk keeps the tcp and udp settings in the same place
+    pub proto: *mut esp_tcp,
+    /** A callback function that is informed about events for this espconn */
+    pub recv_callback: Option<espconn_recv_callback>,
+    pub sent_callback: Option<espconn_sent_callback>,
+    pub link_cnt: u8,
+    pub reverse: *mut u32,
+}
+
+#[repr(u32)]
+pub enum espconn_state {
+    ESPCONN_NONE = 0,
+    ESPCONN_WAIT = 1,
+    ESPCONN_LISTEN = 2,
+    ESPCONN_CONNECT = 3,
+    ESPCONN_WRITE = 4,
+    ESPCONN_READ = 5,
+    ESPCONN_CLOSE = 6
+}
+
+#[repr(u32)]
+pub enum espconn_type {
+    ESPCONN_INVALID    = 0,
+    /* ESPCONN_TCP Group */
+    ESPCONN_TCP        = 0x10,
+    /* ESPCONN_UDP Group */
+    ESPCONN_UDP        = 0x20,
+}
+
+#[repr(u32)]
+pub enum espconn_level {
+    ESPCONN_KEEPIDLE = 0,
+    ESPCONN_KEEPINTVL = 1,
+    ESPCONN_KEEPCNT = 2,
+}
+
+pub const ESPCONN_KEEPALIVE: u32 = 0x08;
+
+extern "C" {
+    pub fn espconn_regist_connectcb(espconn: *mut espconn, connect_cb: espconn_connect_callback) -> i8;
+    pub fn espconn_regist_disconcb(espconn: *mut espconn, discon_cb: espconn_connect_callback) -> i8;
+    pub fn espconn_regist_recvcb(espconn: *mut espconn, recv_cb: espconn_recv_callback) -> i8;
+    pub fn espconn_regist_sentcb(espconn: *mut espconn, sent_cb: espconn_sent_callback) -> i8;
+    pub fn espconn_accept(espconn: *mut espconn) -> i8;
+    pub fn espconn_connect(espconn: *mut espconn) -> i8;
+    pub fn espconn_disconnect(espconn: *mut espconn) -> i8;
+    pub fn espconn_send(espconn: *mut espconn, psent: *const u8, length: u16) -> i8;
+    pub fn espconn_set_opt(espconn: *mut espconn, opt: u32) -> i8;
+    pub fn espconn_set_keepalive(espconn: *mut espconn, level: u32, optarg: *const u32) -> i8;
+    pub fn espconn_create(espconn: *mut espconn) -> i8;
+    pub fn espconn_delete(espconn: *mut espconn) -> i8;
+    pub fn espconn_port() -> u32;
+    pub fn espconn_get_connection_info(espconn: *mut espconn, info: *mut *mut remot_info, typeflags: u8) -> i8;
+}
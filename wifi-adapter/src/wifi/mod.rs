@@ -99,6 +99,7 @@ extern "C" {
     pub fn wifi_get_ip_info(if_index: u8, info: *mut ip_info) -> u8;
     pub fn wifi_set_ip_info(if_index: u8, info: *mut ip_info) -> u8;
     pub fn wifi_station_get_connect_status() -> u8;
+    pub fn wifi_station_get_rssi() -> i8;
     pub fn ets_memcpy(dst:*mut u8, src:*const u8,size:u32);
     pub fn ets_memset(dst:*mut u8, val:u8,size:u32);
     pub fn wifi_station_disconnect() -> u8;
@@ -163,7 +164,8 @@ pub fn connect(ssid: &str, passwd: &str) -> i32 {
 }
 
 
-pub fn setup_server(ssid: &str, passwd: &str) -> i32 {
+/// channel is 1 to 13
+pub fn setup_server(ssid: &str, passwd: &str, channel: u8) -> i32 {
 
     unsafe {
         if wifi_set_opmode( SOFTAP_MODE ) == 0 {
@@ -175,7 +177,7 @@ pub fn setup_server(ssid: &str, passwd: &str) -> i32 {
         ssid: [0;32],
         password: [0;64],
         ssid_len: ssid.len() as u8,    // Note: Recommend to set it according to your ssid
-        channel: 7,    // Note: support 1 ~ 13
+        channel: channel,    // Note: support 1 ~ 13
         authmode: 4,    // Note: Don't support AUTH_WEP in softAP mode.
         ssid_hidden: 0,    // Note: default 0
         max_connection: 4,    // Note: default 4, max 4
@@ -185,13 +187,15 @@ pub fn setup_server(ssid: &str, passwd: &str) -> i32 {
     unsafe {
         wifi_softap_get_config(& mut station_conf);
 
-        ets_memcpy(station_conf.ssid.as_mut_ptr(), ssid.as_ptr(), 32);
-        ets_memcpy(station_conf.password.as_mut_ptr(), passwd.as_ptr(), 32);
+        // The saved config has the length of the last ssid, the new one can be shorter
+        ets_memcpy(station_conf.ssid.as_mut_ptr(), ssid.as_ptr(), ssid.len() as u32);
+        ets_memcpy(station_conf.password.as_mut_ptr(), passwd.as_ptr(), passwd.len() as u32);
 
         station_conf.ssid[ssid.len()] = '\0' as u8;
         station_conf.password[passwd.len()] = '\0' as u8;
+        station_conf.ssid_len = ssid.len() as u8;
 
-        station_conf.channel = 7;
+        station_conf.channel = channel;
         station_conf.ssid_hidden = 0;
 
     };
@@ -248,12 +252,23 @@ pub fn setup_server(ssid: &str, passwd: &str) -> i32 {
     return 0;
 }
 
+pub fn disconnect() {
+    unsafe { wifi_station_disconnect(); };
+}
+
 pub fn is_connected() -> u8 {
     unsafe {
         return wifi_station_get_connect_status();
     };
 }
 
+/// dBm of the access point, 31 when it can not be read
+pub fn rssi() -> i8 {
+    unsafe {
+        return wifi_station_get_rssi();
+    };
+}
+
 pub fn get_ip() -> u32 {
     let mut ipconfig = ip_info {
         ip: ip_addr { addr: 0 },